@@ -1,34 +1,59 @@
-use sdl2::audio::{AudioCallback, AudioSpecDesired, AudioStatus};
+use chip8forever::sound::{SoundEvent, SoundTimeline, Synth, Tone};
+use log::{debug, warn};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 use sdl2::Sdl;
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
+struct Beeper {
+    synth: Synth,
+    timeline: SoundTimeline,
+    paused: bool,
 }
 
-impl AudioCallback for SquareWave {
+impl AudioCallback for Beeper {
     type Channel = f32;
 
+    //The synth follows the emulated sound state sample by sample.
     fn callback(&mut self, out: &mut [Self::Channel]) {
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+            let state = self.timeline.next_state();
+            self.synth.set_gate(state.on && !self.paused);
+            self.synth.set_pattern(state.pattern, state.pitch);
+            *x = self.synth.next_sample();
         }
     }
 }
 
+//The device keeps playing all the time, the synth fades the beeper in and out.
+//Without a device everything is silently dropped.
 pub struct AudioSubsystem {
-    device: sdl2::audio::AudioDevice<SquareWave>,
+    device: Option<sdl2::audio::AudioDevice<Beeper>>,
+    muted: bool,
 }
 
 impl AudioSubsystem {
-    pub fn new(sdl2_context: &Sdl) -> AudioSubsystem {
-        let sdl2_audio = sdl2_context.audio().unwrap();
+    //Falls back to no sound with a warning when there is no audio device.
+    pub fn new(sdl2_context: &Sdl, tone: Tone) -> AudioSubsystem {
+        match AudioSubsystem::open(sdl2_context, tone) {
+            Ok(device) => AudioSubsystem {
+                device: Some(device),
+                muted: false,
+            },
+            Err(e) => {
+                warn!("No audio ({}), running without sound", e);
+                AudioSubsystem::null()
+            }
+        }
+    }
+
+    pub fn null() -> AudioSubsystem {
+        AudioSubsystem {
+            device: None,
+            muted: false,
+        }
+    }
+
+    fn open(sdl2_context: &Sdl, tone: Tone) -> Result<AudioDevice<Beeper>, String> {
+        let sdl2_audio = sdl2_context.audio()?;
 
         //Default audio spec.
         let desired_spec = AudioSpecDesired {
@@ -37,29 +62,42 @@ impl AudioSubsystem {
             samples: None,     // default sample size
         };
 
-        let device = sdl2_audio
-            .open_playback(None, &desired_spec, |spec| {
-                println!("{:?}", spec);
-                SquareWave {
-                    phase_inc: 440.0 / spec.freq as f32,
-                    phase: 0.0,
-                    volume: 0.25,
-                }
-            })
-            .unwrap(); // No error handling :(
+        let device = sdl2_audio.open_playback(None, &desired_spec, |spec| {
+            debug!("{:?}", spec);
+            Beeper {
+                synth: Synth::new(tone, spec.freq as u32),
+                timeline: SoundTimeline::new(spec.freq as u32),
+                paused: false,
+            }
+        })?;
+        device.resume();
+        Ok(device)
+    }
 
-        AudioSubsystem { device }
+    //Sound timer events from the emulator and the emulated time it has reached.
+    pub fn push_events(&mut self, events: &[SoundEvent], now: f64) {
+        if let Some(device) = self.device.as_mut() {
+            device.lock().timeline.push(events, now);
+        }
     }
 
-    pub fn resume(&mut self) {
-        self.device.resume();
+    //Silences the beeper while the emulator is not running.
+    pub fn set_paused(&mut self, paused: bool) {
+        if let Some(device) = self.device.as_mut() {
+            device.lock().paused = paused;
+        }
     }
 
-    pub fn pause(&mut self) {
-        self.device.pause();
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        if let Some(device) = self.device.as_mut() {
+            device.lock().synth.set_muted(muted);
+        }
     }
 
-    pub fn get_status(&self) -> AudioStatus {
-        self.device.status()
+    //Returns true if the beeper is muted now.
+    pub fn toggle_mute(&mut self) -> bool {
+        self.set_muted(!self.muted);
+        self.muted
     }
 }
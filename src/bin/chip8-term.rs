@@ -0,0 +1,210 @@
+use chip8forever::config::{Config, Layer};
+use chip8forever::cpu::CpuFault;
+use chip8forever::emulator::Emulator;
+use chip8forever::keypad::{Keypad, KEYS};
+use chip8forever::rom::Rom;
+use chip8forever::term::{TermMode, TermRenderer};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::{cursor, queue, terminal};
+use log::warn;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+
+//Terminals only report key presses, so a key stays down this many frames after its last press.
+const KEY_HOLD_FRAMES: u32 = 6;
+const FRAME: Duration = Duration::from_micros(16_667);
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "Chip8Forever-term",
+    about = "Chip8Forever rendering straight into the terminal."
+)]
+struct Options {
+    /// Input file
+    #[structopt(name = "path-to-rom", short = "r", long = "rom", parse(from_os_str))]
+    rom_path: PathBuf,
+
+    /// Pixel packing: halfblock or braille
+    #[structopt(long = "mode", default_value = "halfblock")]
+    mode: TermMode,
+
+    /// Config file to use instead of the one in the user config directory
+    #[structopt(long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+}
+
+//Terminal keys for the config's key names, which are SDL's. Letters, digits
+//and a few named keys have a terminal counterpart.
+fn key_code(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => return Some(KeyCode::Char(c.to_ascii_lowercase())),
+        (None, _) => return None,
+        _ => {}
+    }
+    match name.to_ascii_lowercase().as_str() {
+        "space" => Some(KeyCode::Char(' ')),
+        "up" => Some(KeyCode::Up),
+        "down" => Some(KeyCode::Down),
+        "left" => Some(KeyCode::Left),
+        "right" => Some(KeyCode::Right),
+        "return" => Some(KeyCode::Enter),
+        "tab" => Some(KeyCode::Tab),
+        "backspace" => Some(KeyCode::Backspace),
+        _ => None,
+    }
+}
+
+//Terminal key and keypad key pairs for config.keys.
+fn keymap(keys: &[String]) -> Vec<(KeyCode, u8)> {
+    let mut keymap = Vec::new();
+    for (key, name) in keys.iter().enumerate().take(KEYS) {
+        match key_code(name) {
+            Some(code) => keymap.push((code, key as u8)),
+            None => warn!(
+                "No terminal key for {}, keypad key {:X} is unbound",
+                name, key
+            ),
+        }
+    }
+    keymap
+}
+
+//Frames left until each key is released.
+struct HeldKeys {
+    frames_left: [u32; KEYS],
+}
+
+impl HeldKeys {
+    fn press(&mut self, key: u8) {
+        self.frames_left[key as usize] = KEY_HOLD_FRAMES;
+    }
+
+    fn tick(&mut self, keypad: &mut Keypad) {
+        for (key, frames) in self.frames_left.iter_mut().enumerate() {
+            keypad.set(key as u8, *frames > 0);
+            *frames = frames.saturating_sub(1);
+        }
+    }
+}
+
+//Returns false once the user asked to quit.
+fn poll_input(held: &mut HeldKeys, keymap: &[(KeyCode, u8)]) -> crossterm::Result<bool> {
+    while event::poll(Duration::from_secs(0))? {
+        if let Event::Key(KeyEvent { code, modifiers }) = event::read()? {
+            match code {
+                KeyCode::Esc => return Ok(false),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(false)
+                }
+                KeyCode::Char(c) => press(held, keymap, KeyCode::Char(c.to_ascii_lowercase())),
+                code => press(held, keymap, code),
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn press(held: &mut HeldKeys, keymap: &[(KeyCode, u8)], code: KeyCode) {
+    for (_, key) in keymap.iter().filter(|(mapped, _)| *mapped == code) {
+        held.press(*key);
+    }
+}
+
+//Returns the fault that stopped the emulator, if any.
+fn run(
+    emulator: &mut Emulator,
+    renderer: &TermRenderer,
+    keymap: &[(KeyCode, u8)],
+) -> crossterm::Result<Option<CpuFault>> {
+    let mut stdout = io::stdout();
+    let mut held = HeldKeys {
+        frames_left: [0; KEYS],
+    };
+    loop {
+        let frame_start = Instant::now();
+        if !poll_input(&mut held, keymap)? {
+            return Ok(None);
+        }
+        held.tick(emulator.keypad_mut());
+        if let Err(fault) = emulator.run_frame() {
+            return Ok(Some(fault));
+        }
+
+        queue!(stdout, cursor::MoveTo(0, 0))?;
+        stdout.write_all(renderer.render(emulator.pixels()).as_bytes())?;
+        stdout.flush()?;
+
+        if let Some(left) = FRAME.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(left);
+        }
+    }
+}
+
+//Same settings as the SDL frontend: the ROM's profile, then the config file.
+fn load(opt: &Options) -> Result<(Emulator, Config), String> {
+    let rom = Rom::from_file_entry(&opt.rom_path, None).map_err(|e| e.to_string())?;
+    let config =
+        Config::load(opt.config.as_deref(), &rom, &Layer::default()).map_err(|e| e.to_string())?;
+    let mut emulator = Emulator::new();
+    emulator.configure(&config).map_err(|e| e.to_string())?;
+    emulator.load(&rom).map_err(|e| e.to_string())?;
+    Ok((emulator, config))
+}
+
+fn main() {
+    //Warnings only, anything more would scroll through the screen.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let opt = Options::from_args();
+    let (mut emulator, config) = match load(&opt) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut renderer = TermRenderer::new(opt.mode);
+    renderer.set_colors(config.palette.foreground, config.palette.background);
+    let keymap = keymap(&config.keys);
+
+    let mut stdout = io::stdout();
+    let result = terminal::enable_raw_mode()
+        .and_then(|_| crossterm::execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide))
+        .and_then(|_| run(&mut emulator, &renderer, &keymap));
+    let _ = crossterm::execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+
+    match result {
+        Ok(None) => {}
+        Ok(Some(fault)) => {
+            eprintln!("CPU fault: {}", fault);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Terminal error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{key_code, keymap};
+    use chip8forever::config::Config;
+    use crossterm::event::KeyCode;
+
+    #[test]
+    fn keymap_test() {
+        let mut keys = Config::default().keys;
+        keys[1] = "Up".to_string();
+        keys[2] = "Left Shift".to_string();
+        let keymap = keymap(&keys);
+        assert!(keymap.contains(&(KeyCode::Char('x'), 0x0)));
+        assert!(keymap.contains(&(KeyCode::Up, 0x1)));
+        assert!(keymap.contains(&(KeyCode::Char('v'), 0xF)));
+        assert!(!keymap.iter().any(|(_, key)| *key == 0x2));
+        assert_eq!(key_code("Space"), Some(KeyCode::Char(' ')));
+    }
+}
@@ -0,0 +1,103 @@
+use chip8forever::disasm::disassemble;
+use chip8forever::trace::{first_divergence, TraceEntry};
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "Chip8Forever-tracediff",
+    about = "Finds the first instruction where two --trace files disagree."
+)]
+struct Options {
+    /// Our trace
+    #[structopt(parse(from_os_str))]
+    ours: PathBuf,
+
+    /// Trace to compare against, from another emulator or quirks profile
+    #[structopt(parse(from_os_str))]
+    theirs: PathBuf,
+
+    /// Matching instructions shown before the divergence
+    #[structopt(short = "c", long = "context", default_value = "5")]
+    context: usize,
+}
+
+//Entries with the line numbers they came from. Blank lines and '#' comments are skipped.
+struct Trace {
+    entries: Vec<TraceEntry>,
+    lines: Vec<usize>,
+}
+
+fn read_trace(path: &Path) -> Result<Trace, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let mut trace = Trace {
+        entries: Vec::new(),
+        lines: Vec::new(),
+    };
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = line
+            .parse()
+            .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+        trace.entries.push(entry);
+        trace.lines.push(number + 1);
+    }
+    Ok(trace)
+}
+
+fn report(ours: &Trace, theirs: &Trace, index: usize, context: usize) {
+    println!("Traces diverge at instruction {}", index + 1);
+    for i in index.saturating_sub(context)..index {
+        println!("  {:>8}  {}", ours.lines[i], ours.entries[i]);
+    }
+    match ours.entries.get(index) {
+        Some(entry) => println!("- {:>8}  {}", ours.lines[index], entry),
+        None => println!("- {:>8}  <end of trace>", ""),
+    }
+    match theirs.entries.get(index) {
+        Some(entry) => println!("+ {:>8}  {}", theirs.lines[index], entry),
+        None => println!("+ {:>8}  <end of trace>", ""),
+    }
+
+    if let (Some(a), Some(b)) = (ours.entries.get(index), theirs.entries.get(index)) {
+        println!("State delta: {}", a.delta(b).join(", "));
+        //Entries hold the state before an instruction runs, so a changed
+        //state means the previous instruction did something different.
+        if index > 0 {
+            let previous = &ours.entries[index - 1];
+            println!(
+                "Suspect: {} ({:04X}) at {:03X} on line {}",
+                disassemble(previous.opcode),
+                previous.opcode,
+                previous.pc,
+                ours.lines[index - 1]
+            );
+        }
+    }
+}
+
+//Exits like diff: 0 when identical, 1 when the traces differ, 2 on errors.
+fn main() {
+    let opt = Options::from_args();
+    let traces = read_trace(&opt.ours).and_then(|ours| Ok((ours, read_trace(&opt.theirs)?)));
+    let (ours, theirs) = match traces {
+        Ok(traces) => traces,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    match first_divergence(&ours.entries, &theirs.entries) {
+        None => println!("Traces match, {} instructions", ours.entries.len()),
+        Some(index) => {
+            report(&ours, &theirs, index, opt.context);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::memmap::MemoryMap;
+use crate::palette::Palette;
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::romdb::{Profile, Source};
+use serde::Deserialize;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::io::Read;
+
+#[derive(Debug, Snafu)]
+pub enum CartridgeError {
+    #[snafu(display("Could not decode cartridge image: {}", source))]
+    ImageError { source: gif::DecodingError },
+    #[snafu(display("Cartridge payload is truncated"))]
+    Truncated,
+    #[snafu(display("Cartridge payload is not valid: {}", source))]
+    PayloadError { source: serde_json::Error },
+}
+
+//Octo "cartridges" are GIF images with the program and its options hidden in
+//the pixels. The low two bits of each palette index, over all frames in order,
+//are read four at a time into bytes, most significant first. The first four bytes
+//are a big endian length, then comes that much JSON.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cartridge {
+    //Octo source code, not a binary.
+    pub program: String,
+    #[serde(default)]
+    pub options: Options,
+}
+
+//The subset of Octo's options this emulator has settings for.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Options {
+    pub tickrate: Option<u32>,
+    pub fill_color: Option<String>,
+    pub background_color: Option<String>,
+    #[serde(default)]
+    pub shift_quirks: bool,
+    #[serde(default)]
+    pub load_store_quirks: bool,
+    #[serde(default)]
+    pub jump_quirks: bool,
+    #[serde(default)]
+    pub logic_quirks: bool,
+    //Largest program Octo allows, 3216 for the VIP, 3583 for SCHIP or 65024 for XO-CHIP.
+    pub max_size: Option<u32>,
+}
+
+impl Cartridge {
+    pub fn from_reader<R: Read>(reader: R) -> Result<Cartridge, CartridgeError> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(reader).context(ImageError)?;
+        let mut pairs = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().context(ImageError)? {
+            pairs.extend(frame.buffer.iter().map(|index| index & 3));
+        }
+        let bytes: Vec<u8> = pairs
+            .chunks_exact(4)
+            .map(|bits| bits[0] << 6 | bits[1] << 4 | bits[2] << 2 | bits[3])
+            .collect();
+
+        let header = bytes.get(..4).context(Truncated)?;
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        ensure!(size <= bytes.len() - 4, Truncated);
+        serde_json::from_slice(&bytes[4..4 + size]).context(PayloadError)
+    }
+
+    //Octo's options as quirks, speed and colours. Octo has no setting for I
+    //advancing by X, so the load/store quirk only switches between X+1 and unchanged.
+    pub fn profile(&self) -> Profile {
+        let options = &self.options;
+        let palette = match (&options.fill_color, &options.background_color) {
+            (Some(fill), Some(background)) => format!(
+                "{},{}",
+                fill.trim_start_matches('#'),
+                background.trim_start_matches('#')
+            )
+            .parse::<Palette>()
+            .ok(),
+            _ => None,
+        };
+        Profile {
+            title: None,
+            authors: Vec::new(),
+            platform: "octo".to_string(),
+            quirks: Some(Quirks {
+                shift_vy: !options.shift_quirks,
+                memory_increment: match options.load_store_quirks {
+                    true => MemoryIncrement::Unchanged,
+                    false => MemoryIncrement::XPlusOne,
+                },
+                vf_reset: options.logic_quirks,
+                jump_vx: options.jump_quirks,
+            }),
+            memory_map: options.max_size.map(|size| match size {
+                0..=3232 => MemoryMap::vip(),
+                3233..=3584 => MemoryMap::schip(),
+                _ => MemoryMap::xochip(),
+            }),
+            tickrate: options.tickrate,
+            keys: Vec::new(),
+            palette,
+            source: Source::Cartridge,
+            supported: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{Cartridge, CartridgeError};
+    use crate::memmap::MemoryMap;
+    use crate::quirks::MemoryIncrement;
+    use crate::rom::Rom;
+
+    //Packs a payload the way Octo does, into a 32x32 two frame image.
+    fn cartridge(json: &str) -> Vec<u8> {
+        let mut bytes = (json.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(json.as_bytes());
+        let mut indices: Vec<u8> = bytes
+            .iter()
+            .flat_map(|byte| vec![byte >> 6, byte >> 4 & 3, byte >> 2 & 3, byte & 3])
+            .map(|bits| bits | 4)
+            .collect();
+        indices.resize(2048, 4);
+
+        let palette = [0u8; 8 * 3];
+        let mut image = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut image, 32, 32, &palette).unwrap();
+            for frame in indices.chunks(1024) {
+                let frame = gif::Frame::from_indexed_pixels(32, 32, frame, None);
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn options_test() {
+        let image = cartridge(
+            r##"{"program":": main\n  loop again","options":{"tickrate":20,
+            "fillColor":"#FFCC00","backgroundColor":"#996600",
+            "shiftQuirks":true,"loadStoreQuirks":true,"maxSize":65024}}"##,
+        );
+        let cartridge = Cartridge::from_reader(&image[..]).unwrap();
+        assert_eq!(cartridge.program, ": main\n  loop again");
+        let profile = cartridge.profile();
+        assert_eq!(profile.tickrate, Some(20));
+        assert_eq!(profile.memory_map, Some(MemoryMap::xochip()));
+        assert_eq!(profile.palette, Some("octo".parse().unwrap()));
+        let quirks = profile.quirks.unwrap();
+        assert!(!quirks.shift_vy);
+        assert_eq!(quirks.memory_increment, MemoryIncrement::Unchanged);
+        assert!(!quirks.jump_vx);
+        assert_eq!(profile.to_string(), "Octo cartridge");
+
+        let rom = Rom::from_cartridge(&image[..]).unwrap();
+        assert_eq!(rom.as_bytes(), &[0x12, 0x02, 0x12, 0x02]);
+        assert_eq!(rom.profile(), Some(&profile));
+    }
+
+    #[test]
+    fn bad_cartridge_test() {
+        let mut image = cartridge("{}");
+        assert!(matches!(
+            Cartridge::from_reader(&image[..]),
+            Err(CartridgeError::PayloadError { .. })
+        ));
+        image.truncate(6);
+        assert!(matches!(
+            Cartridge::from_reader(&image[..]),
+            Err(CartridgeError::ImageError { .. })
+        ));
+    }
+}
@@ -0,0 +1,289 @@
+use crate::cpu::CpuFault;
+use crate::emulator::{Emulator, MAX_INSTRUCTIONS_PER_FRAME};
+use crate::rom::{Rom, RomError};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//Emulated frames per displayed frame while fast-forwarding.
+pub const FAST_FORWARD_FRAMES: u32 = 8;
+
+//Runtime controls. Hotkeys map to these, a debugger or a script can send them too.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Quit,
+    TogglePause,
+    //Runs a single frame, pausing first if needed.
+    FrameAdvance,
+    FastForward(bool),
+    SpeedUp,
+    SlowDown,
+    Reset,
+    SaveState,
+    ToggleFullscreen,
+    ToggleRecording,
+    Screenshot,
+    ToggleStats,
+    ToggleMute,
+}
+
+//What became of a command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Handled {
+    //Done, with a message worth showing when there is one.
+    Done(Option<String>),
+    //About the window, sound or recording, up to the frontend.
+    Frontend(Command),
+}
+
+//The emulator with a ROM, run by commands. Frontends own one and handle the
+//commands it hands back.
+pub struct Controller {
+    emulator: Emulator,
+    rom: Option<Rom>,
+    fault: Option<CpuFault>,
+    state_dir: PathBuf,
+    paused: bool,
+    advance: bool,
+    fast_forward: bool,
+    quit: bool,
+}
+
+impl Controller {
+    pub fn new(emulator: Emulator) -> Controller {
+        Controller {
+            emulator,
+            rom: None,
+            fault: None,
+            state_dir: PathBuf::from("."),
+            paused: false,
+            advance: false,
+            fast_forward: false,
+            quit: false,
+        }
+    }
+
+    //Where save states go.
+    pub fn set_state_dir(&mut self, dir: &Path) {
+        self.state_dir = dir.to_path_buf();
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Emulator {
+        &mut self.emulator
+    }
+
+    pub fn rom(&self) -> Option<&Rom> {
+        self.rom.as_ref()
+    }
+
+    //Starts the ROM over, clearing any fault. Settings and pause stay as they are,
+    //and so does the old ROM when the new one does not fit.
+    pub fn load(&mut self, rom: Rom) -> Result<(), RomError> {
+        self.emulator.load(&rom)?;
+        self.rom = Some(rom);
+        self.fault = None;
+        Ok(())
+    }
+
+    pub fn fault(&self) -> Option<&CpuFault> {
+        self.fault.as_ref()
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn fast_forward(&self) -> bool {
+        self.fast_forward
+    }
+
+    pub fn quit(&self) -> bool {
+        self.quit
+    }
+
+    //Neither paused nor stopped by a fault.
+    pub fn running(&self) -> bool {
+        self.fault.is_none() && !self.paused
+    }
+
+    pub fn execute(&mut self, command: Command) -> Handled {
+        let message = match command {
+            Command::Quit => {
+                self.quit = true;
+                None
+            }
+            Command::TogglePause => {
+                self.paused = !self.paused;
+                None
+            }
+            Command::FrameAdvance => {
+                match self.paused {
+                    true => self.advance = true,
+                    false => self.paused = true,
+                }
+                None
+            }
+            Command::FastForward(on) => {
+                self.fast_forward = on;
+                None
+            }
+            Command::SpeedUp => Some(self.change_speed(1)),
+            Command::SlowDown => Some(self.change_speed(-1)),
+            Command::Reset => Some(self.reset()),
+            Command::SaveState => Some(self.save_state()),
+            _ => return Handled::Frontend(command),
+        };
+        Handled::Done(message)
+    }
+
+    fn change_speed(&mut self, delta: i32) -> String {
+        let current = self.emulator.instructions_per_frame() as i32;
+        let speed = (current + delta).clamp(1, MAX_INSTRUCTIONS_PER_FRAME as i32) as u32;
+        self.emulator.set_instructions_per_frame(speed);
+        format!("{} instructions per frame", speed)
+    }
+
+    //Reloads the ROM, clearing any fault.
+    fn reset(&mut self) -> String {
+        if let Some(rom) = self.rom.as_ref() {
+            if let Err(e) = self.emulator.load(rom) {
+                return e.to_string();
+            }
+        }
+        self.fault = None;
+        "Reset".to_string()
+    }
+
+    //Saves state-<millis>.c8s in the state directory, for --load-state.
+    fn save_state(&mut self) -> String {
+        let filename = self
+            .state_dir
+            .join(format!("state-{}.c8s", timestamp_millis()));
+        match self.emulator.save_state().save(&filename) {
+            Ok(()) => format!("Saved {}", filename.display()),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn frames_to_run(&self) -> u32 {
+        if self.fault.is_some() {
+            0
+        } else if self.paused {
+            self.advance as u32
+        } else if self.fast_forward {
+            FAST_FORWARD_FRAMES
+        } else {
+            1
+        }
+    }
+
+    //Runs the frames due for one displayed frame and returns the instructions executed.
+    //A fault halts the emulator until the next reset or load.
+    pub fn run(&mut self) -> Result<u32, CpuFault> {
+        let frames = self.frames_to_run();
+        self.advance = false;
+        let mut instructions = 0;
+        for _ in 0..frames {
+            if let Err(fault) = self.emulator.run_frame() {
+                self.fault = Some(fault.clone());
+                return Err(fault);
+            }
+            instructions += self.emulator.instructions_per_frame();
+        }
+        Ok(instructions)
+    }
+}
+
+//For file names that do not clash.
+pub fn timestamp_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command::{Command, Controller, Handled};
+    use crate::cpu::CpuFault;
+    use crate::emulator::Emulator;
+    use crate::rom::{Rom, RomError};
+
+    //V0 += 1 forever.
+    fn controller() -> Controller {
+        let mut controller = Controller::new(Emulator::new());
+        controller
+            .load(Rom::new(vec![0x70, 0x01, 0x12, 0x00]).unwrap())
+            .unwrap();
+        controller.emulator_mut().set_instructions_per_frame(2);
+        controller
+    }
+
+    #[test]
+    fn pause_and_advance_test() {
+        let mut controller = controller();
+        assert_eq!(controller.run(), Ok(2));
+        controller.execute(Command::FrameAdvance);
+        assert!(controller.paused());
+        assert_eq!(controller.run(), Ok(0));
+        controller.execute(Command::FrameAdvance);
+        assert_eq!(controller.run(), Ok(2));
+        assert_eq!(controller.run(), Ok(0));
+        controller.execute(Command::TogglePause);
+        controller.execute(Command::FastForward(true));
+        assert_eq!(controller.run(), Ok(16));
+    }
+
+    #[test]
+    fn speed_test() {
+        let mut controller = controller();
+        controller.emulator_mut().set_instructions_per_frame(1);
+        assert_eq!(
+            controller.execute(Command::SlowDown),
+            Handled::Done(Some("1 instructions per frame".to_string()))
+        );
+        controller.execute(Command::SpeedUp);
+        assert_eq!(controller.emulator().instructions_per_frame(), 2);
+    }
+
+    #[test]
+    fn fault_and_reset_test() {
+        let mut controller = Controller::new(Emulator::new());
+        controller
+            .load(Rom::new(vec![0x00, 0xEE]).unwrap())
+            .unwrap();
+        assert_eq!(
+            controller.run(),
+            Err(CpuFault::StackUnderflow { pc: 0x200 })
+        );
+        assert!(!controller.running());
+        assert_eq!(controller.run(), Ok(0));
+        controller.execute(Command::Reset);
+        assert!(controller.running());
+    }
+
+    #[test]
+    fn too_large_test() {
+        let mut controller = controller();
+        assert!(matches!(
+            controller.load(Rom::new(vec![0; 0xCA1]).unwrap()),
+            Err(RomError::TooLarge { size: 0xCA1, .. })
+        ));
+        assert_eq!(controller.rom().unwrap().as_bytes().len(), 4);
+        assert_eq!(controller.run(), Ok(2));
+    }
+
+    #[test]
+    fn frontend_commands_test() {
+        let mut controller = controller();
+        assert_eq!(
+            controller.execute(Command::Screenshot),
+            Handled::Frontend(Command::Screenshot)
+        );
+        controller.execute(Command::Quit);
+        assert!(controller.quit());
+    }
+}
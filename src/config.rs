@@ -0,0 +1,446 @@
+use crate::emulator::MAX_INSTRUCTIONS_PER_FRAME;
+use crate::font::BuiltinFont;
+use crate::mem::OutOfRange;
+use crate::memmap::MemoryMap;
+use crate::palette::Palette;
+use crate::quirks::{QuirkOverride, Quirks};
+use crate::rom::{Rom, RomError};
+use crate::sound::{Tone, Waveform};
+use log::{info, warn};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use snafu::{ensure, ResultExt, Snafu};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+//Default keyboard layout, indexed by keypad key:
+//1 2 3 C     1 2 3 4
+//4 5 6 D  => Q W E R
+//7 8 9 E     A S D F
+//A 0 B F     Z X C V
+const DEFAULT_KEYS: [&str; 16] = [
+    "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+];
+
+#[derive(Debug, Snafu)]
+pub enum ConfigError {
+    #[snafu(display("Could not read config {}: {}", filename.display(), source))]
+    ReadError {
+        filename: PathBuf,
+        source: io::Error,
+    },
+    #[snafu(display("Error in config {}: {}", filename.display(), source))]
+    ParseError {
+        filename: PathBuf,
+        source: toml::de::Error,
+    },
+    #[snafu(display(
+        "Instructions per frame must be between 1 and {}, got {}",
+        MAX_INSTRUCTIONS_PER_FRAME,
+        ipf
+    ))]
+    BadSpeed { ipf: u32 },
+    #[snafu(display("Beeper frequency must be a positive number of Hz, got {}", frequency))]
+    BadFrequency { frequency: f32 },
+    #[snafu(display("{}", source))]
+    DoesNotFit { source: RomError },
+}
+
+//Effective settings after every layer has been applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub palette: Palette,
+    pub quirks: Quirks,
+    pub memory_map: MemoryMap,
+    pub font: BuiltinFont,
+    //What happens when a program reaches past the end of memory.
+    pub out_of_range: OutOfRange,
+    //Used instead of the built-in font when set.
+    pub font_file: Option<PathBuf>,
+    pub instructions_per_frame: u32,
+    pub tone: Tone,
+    pub mute: bool,
+    //Keyboard key names for keypad keys 0 to F.
+    pub keys: Vec<String>,
+    pub state_dir: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            palette: Palette::default(),
+            quirks: Quirks::default(),
+            memory_map: MemoryMap::default(),
+            font: BuiltinFont::default(),
+            out_of_range: OutOfRange::default(),
+            font_file: None,
+            instructions_per_frame: crate::emulator::DEFAULT_INSTRUCTIONS_PER_FRAME,
+            tone: Tone::default(),
+            mute: false,
+            keys: DEFAULT_KEYS.iter().map(|key| key.to_string()).collect(),
+            state_dir: PathBuf::from("."),
+        }
+    }
+}
+
+impl Config {
+    //Built-in defaults, the config file, its section for this ROM, then the overrides.
+    //Without a path the default config file is used if there is one.
+    pub fn load(path: Option<&Path>, rom: &Rom, overrides: &Layer) -> Result<Config, ConfigError> {
+        let file = match (path, ConfigFile::default_path()) {
+            (Some(path), _) => ConfigFile::load(path)?,
+            (None, Some(path)) => ConfigFile::load_optional(path)?,
+            (None, None) => ConfigFile::default(),
+        };
+        let detected = match rom.profile() {
+            Some(profile) => {
+                match profile.supported {
+                    true => info!("{}", profile),
+                    false => warn!("{}", profile),
+                }
+                profile.layer()
+            }
+            None => Layer::default(),
+        };
+        let mut config = file.resolve(&rom.sha1(), &detected);
+        config.apply(overrides);
+        config.validate()?;
+        rom.fits(&config.memory_map).context(DoesNotFit)?;
+        Ok(config)
+    }
+
+    //Values that parse but can not be used.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let ipf = self.instructions_per_frame;
+        ensure!(
+            (1..=MAX_INSTRUCTIONS_PER_FRAME).contains(&ipf),
+            BadSpeed { ipf }
+        );
+        let frequency = self.tone.frequency;
+        ensure!(
+            frequency.is_finite() && frequency > 0.0,
+            BadFrequency { frequency }
+        );
+        Ok(())
+    }
+
+    pub fn apply(&mut self, layer: &Layer) {
+        if let Some(palette) = layer.palette {
+            self.palette = palette;
+        }
+        if let Some(quirks) = layer.quirks {
+            self.quirks = quirks;
+        }
+        for quirk in &layer.quirk {
+            quirk.apply(&mut self.quirks);
+        }
+        if let Some(memory_map) = layer.memory_map {
+            self.memory_map = memory_map;
+        }
+        //Picking a built-in font drops a font file from the layers below.
+        if let Some(font) = layer.font {
+            self.font = font;
+            self.font_file = None;
+        }
+        if let Some(font_file) = &layer.font_file {
+            self.font_file = Some(font_file.clone());
+        }
+        if let Some(out_of_range) = layer.out_of_range {
+            self.out_of_range = out_of_range;
+        }
+        if let Some(ipf) = layer.ipf {
+            self.instructions_per_frame = ipf;
+        }
+        if let Some(waveform) = layer.audio.waveform {
+            self.tone.waveform = waveform;
+        }
+        if let Some(frequency) = layer.audio.frequency {
+            self.tone.frequency = frequency;
+        }
+        if let Some(volume) = layer.audio.volume {
+            self.tone.volume = volume.clamp(0.0, 1.0);
+        }
+        if let Some(mute) = layer.audio.mute {
+            self.mute = mute;
+        }
+        for (key, name) in &layer.keys {
+            self.keys[*key as usize] = name.clone();
+        }
+        if let Some(state_dir) = &layer.state_dir {
+            self.state_dir = state_dir.clone();
+        }
+    }
+}
+
+//Written in the config file format, so the output can be used as a config file.
+impl Display for Config {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let quirks: Vec<String> = self
+            .quirks
+            .overrides()
+            .iter()
+            .map(|quirk| format!("\"{}\"", quirk))
+            .collect();
+        writeln!(f, "palette = \"{}\"", self.palette)?;
+        writeln!(f, "quirk = [{}]", quirks.join(", "))?;
+        writeln!(f, "memory_map = \"{}\"", self.memory_map)?;
+        writeln!(f, "font = \"{}\"", self.font)?;
+        if let Some(font_file) = &self.font_file {
+            writeln!(f, "font_file = {:?}", font_file.display().to_string())?;
+        }
+        writeln!(f, "out_of_range = \"{}\"", self.out_of_range)?;
+        writeln!(f, "ipf = {}", self.instructions_per_frame)?;
+        writeln!(f, "state_dir = {:?}", self.state_dir.display().to_string())?;
+        writeln!(f)?;
+        writeln!(f, "[audio]")?;
+        writeln!(f, "waveform = \"{}\"", self.tone.waveform)?;
+        writeln!(f, "frequency = {:?}", self.tone.frequency)?;
+        writeln!(f, "volume = {:?}", self.tone.volume)?;
+        writeln!(f, "mute = {}", self.mute)?;
+        writeln!(f)?;
+        writeln!(f, "[keys]")?;
+        for (key, name) in self.keys.iter().enumerate() {
+            writeln!(f, "{:X} = {:?}", key, name)?;
+        }
+        Ok(())
+    }
+}
+
+//One source of settings, anything left out keeps the value from the layer below.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct Layer {
+    #[serde(default, deserialize_with = "parse")]
+    pub palette: Option<Palette>,
+    //A preset replaces all quirks set below, then quirk overrides are applied in order.
+    #[serde(default, deserialize_with = "parse")]
+    pub quirks: Option<Quirks>,
+    #[serde(default, deserialize_with = "parse_all")]
+    pub quirk: Vec<QuirkOverride>,
+    #[serde(default, deserialize_with = "parse")]
+    pub memory_map: Option<MemoryMap>,
+    #[serde(default, deserialize_with = "parse")]
+    pub font: Option<BuiltinFont>,
+    pub font_file: Option<PathBuf>,
+    #[serde(default, deserialize_with = "parse")]
+    pub out_of_range: Option<OutOfRange>,
+    pub ipf: Option<u32>,
+    #[serde(default)]
+    pub audio: AudioLayer,
+    #[serde(default, deserialize_with = "parse_keys")]
+    pub keys: Vec<(u8, String)>,
+    pub state_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct AudioLayer {
+    #[serde(default, deserialize_with = "parse")]
+    pub waveform: Option<Waveform>,
+    pub frequency: Option<f32>,
+    pub volume: Option<f32>,
+    pub mute: Option<bool>,
+}
+
+//The config file: global settings, then [rom.<sha1>] sections for single ROMs.
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    #[serde(flatten)]
+    pub global: Layer,
+    #[serde(default)]
+    pub rom: HashMap<String, Layer>,
+}
+
+impl ConfigFile {
+    pub fn load<T: AsRef<Path>>(path: T) -> Result<ConfigFile, ConfigError> {
+        let filename = path.as_ref();
+        let text = fs::read_to_string(filename).context(ReadError { filename })?;
+        toml::from_str(&text).context(ParseError { filename })
+    }
+
+    //Like load, but a missing file is an empty config.
+    pub fn load_optional<T: AsRef<Path>>(path: T) -> Result<ConfigFile, ConfigError> {
+        match path.as_ref().exists() {
+            true => ConfigFile::load(path),
+            false => Ok(ConfigFile::default()),
+        }
+    }
+
+    //$XDG_CONFIG_HOME/chip8forever/config.toml or the platform equivalent.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip8forever").join("config.toml"))
+    }
+
+    //Defaults, the global settings, what is known about the ROM, then its section.
+    pub fn resolve(&self, rom_sha1: &str, detected: &Layer) -> Config {
+        let mut config = Config::default();
+        config.apply(&self.global);
+        config.apply(detected);
+        if let Some(layer) = self.rom.get(&rom_sha1.to_lowercase()) {
+            config.apply(layer);
+        }
+        config
+    }
+}
+
+fn parse<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    value
+        .map(|value| value.parse().map_err(D::Error::custom))
+        .transpose()
+}
+
+fn parse_all<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let values: Vec<String> = Vec::deserialize(deserializer)?;
+    values
+        .iter()
+        .map(|value| value.parse().map_err(D::Error::custom))
+        .collect()
+}
+
+//Keypad keys are single hex digits. Frontends check the keyboard key names.
+fn parse_keys<'de, D>(deserializer: D) -> Result<Vec<(u8, String)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let keys: HashMap<String, String> = HashMap::deserialize(deserializer)?;
+    let mut parsed = keys
+        .into_iter()
+        .map(|(key, name)| match u8::from_str_radix(&key, 16) {
+            Ok(digit) if key.len() == 1 => Ok((digit, name)),
+            _ => Err(D::Error::custom(format!(
+                "Unknown keypad key {}, use 0 to F",
+                key
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    parsed.sort();
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{Config, ConfigError, ConfigFile, Layer};
+    use crate::mem::OutOfRange;
+    use crate::memmap::MemoryMap;
+    use crate::quirks::{MemoryIncrement, Quirks};
+    use crate::sound::Waveform;
+    use std::path::PathBuf;
+
+    const FILE: &str = r#"
+        palette = "amber"
+        quirks = "schip"
+        ipf = 15
+
+        [audio]
+        waveform = "sine"
+
+        [keys]
+        0 = "Space"
+        a = "Z"
+
+        [rom.0123abcd]
+        ipf = 30
+        memory_map = "eti660"
+        font_file = "fonts/eti.bin"
+        out_of_range = "fault"
+        quirks = "vip"
+        quirk = ["memory-increment=x"]
+    "#;
+
+    #[test]
+    fn layers_test() {
+        let file: ConfigFile = toml::from_str(FILE).unwrap();
+        let global = file.resolve("ffff", &Layer::default());
+        assert_eq!(global.palette, "amber".parse().unwrap());
+        assert_eq!(global.quirks, Quirks::schip());
+        assert_eq!(global.instructions_per_frame, 15);
+        assert_eq!(global.tone.waveform, Waveform::Sine);
+        assert_eq!(global.tone.frequency, 440.0);
+        assert_eq!(global.keys[0], "Space");
+        assert_eq!(global.keys[1], "1");
+
+        let rom = file.resolve("0123ABCD", &Layer::default());
+        assert_eq!(rom.instructions_per_frame, 30);
+        assert_eq!(rom.memory_map, MemoryMap::eti660());
+        assert_eq!(rom.font_file, Some(PathBuf::from("fonts/eti.bin")));
+        assert_eq!(rom.out_of_range, OutOfRange::Fault);
+        assert_eq!(global.memory_map, MemoryMap::vip());
+        assert_eq!(rom.quirks.memory_increment, MemoryIncrement::X);
+        assert!(rom.quirks.shift_vy);
+        assert_eq!(rom.palette, global.palette);
+    }
+
+    #[test]
+    fn command_line_wins_test() {
+        let file: ConfigFile = toml::from_str(FILE).unwrap();
+        let mut config = file.resolve("0123abcd", &Layer::default());
+        config.apply(&Layer {
+            ipf: Some(5),
+            ..Layer::default()
+        });
+        assert_eq!(config.instructions_per_frame, 5);
+        assert_eq!(config.quirks.memory_increment, MemoryIncrement::X);
+    }
+
+    #[test]
+    fn print_round_trip_test() {
+        let file: ConfigFile = toml::from_str(FILE).unwrap();
+        let config = file.resolve("0123abcd", &Layer::default());
+        let printed: ConfigFile = toml::from_str(&config.to_string()).unwrap();
+        assert_eq!(printed.resolve("", &Layer::default()), config);
+        let defaults: ConfigFile = toml::from_str(&Config::default().to_string()).unwrap();
+        assert_eq!(defaults.resolve("", &Layer::default()), Config::default());
+    }
+
+    #[test]
+    fn detected_layer_test() {
+        let file: ConfigFile = toml::from_str(FILE).unwrap();
+        let detected = Layer {
+            ipf: Some(1000),
+            quirks: Some(Quirks::xochip()),
+            ..Layer::default()
+        };
+        let unknown = file.resolve("ffff", &detected);
+        assert_eq!(unknown.instructions_per_frame, 1000);
+        assert_eq!(unknown.quirks, Quirks::xochip());
+        let configured = file.resolve("0123abcd", &detected);
+        assert_eq!(configured.instructions_per_frame, 30);
+    }
+
+    #[test]
+    fn bad_value_test() {
+        assert!(toml::from_str::<ConfigFile>("palette = \"nope\"").is_err());
+        assert!(toml::from_str::<ConfigFile>("quirk = [\"turbo=on\"]").is_err());
+        assert!(toml::from_str::<ConfigFile>("[keys]\n10 = \"A\"").is_err());
+    }
+
+    #[test]
+    fn validate_test() {
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+        config.instructions_per_frame = 0;
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::BadSpeed { ipf: 0 })
+        ));
+        config.instructions_per_frame = 10;
+        for &frequency in &[0.0, -440.0, f32::NAN, f32::INFINITY] {
+            config.tone.frequency = frequency;
+            assert!(matches!(
+                config.validate(),
+                Err(ConfigError::BadFrequency { .. })
+            ));
+        }
+    }
+}
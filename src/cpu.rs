@@ -1,9 +1,13 @@
-use crate::audio::AudioSubsystem;
-use crate::display::{DisplaySubsystem, Sprite};
-use crate::input::{InputSubsystem, KeyboardMapper};
-use crate::mem::Memory;
-use sdl2::audio::AudioStatus;
-use sdl2::hint::set_video_minimize_on_focus_loss;
+use crate::disasm::disassemble;
+use crate::font::FontAddresses;
+use crate::framebuffer::{PixelBuffer, Sprite};
+use crate::keypad::Keypad;
+use crate::mem::{Memory, OutOfRange};
+use crate::memmap::MemoryMap;
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::sound::{SoundState, DEFAULT_PITCH, PATTERN_BYTES};
+use log::{trace, warn};
+use snafu::{ensure, Snafu};
 
 const REGS: usize = 16;
 const STACK_SIZE: usize = 16;
@@ -12,15 +16,15 @@ mod helper {
     pub fn nibbles(bytes: &[u8]) -> (u8, u8, u8, u8) {
         assert!(bytes.len() == 2);
         let o1 = (bytes[0] & 0xF0) >> 4;
-        let o2 = (bytes[0] & 0x0F);
+        let o2 = bytes[0] & 0x0F;
 
         let o3 = (bytes[1] & 0xF0) >> 4;
-        let o4 = (bytes[1] & 0x0F);
+        let o4 = bytes[1] & 0x0F;
         (o1, o2, o3, o4)
     }
 
     pub fn address(bytes: &[u8]) -> u16 {
-        let address: u16 = (((bytes[0] & 0x0F) as u16) << 8) as u16 + bytes[1] as u16;
+        let address: u16 = (((bytes[0] & 0x0F) as u16) << 8) + bytes[1] as u16;
         address
     }
 
@@ -45,6 +49,19 @@ mod helper {
     }
 }
 
+//Program errors that a real interpreter would crash or hang on.
+#[derive(Debug, Snafu, Clone, PartialEq)]
+pub enum CpuFault {
+    #[snafu(display("Unknown opcode {:04X} at {:03X}", opcode, pc))]
+    UnknownOpcode { pc: u16, opcode: u16 },
+    #[snafu(display("Stack overflow at {:03X}", pc))]
+    StackOverflow { pc: u16 },
+    #[snafu(display("Return with empty stack at {:03X}", pc))]
+    StackUnderflow { pc: u16 },
+    #[snafu(display("Access to {:X} past the end of memory at {:03X}", address, pc))]
+    MemoryOutOfRange { pc: u16, address: usize },
+}
+
 pub struct Cpu {
     regs: [u8; REGS],
     i: u16,
@@ -53,8 +70,34 @@ pub struct Cpu {
     st: u8,
     stack: [u16; STACK_SIZE],
     sp: usize,
+    rng: u32,
+    seed: u32,
+    quirks: Quirks,
+    memory_map: MemoryMap,
+    fonts: FontAddresses,
+    out_of_range: OutOfRange,
+    audio_pattern: Option<[u8; PATTERN_BYTES]>,
+    pitch: u8,
+}
+
+//Everything a save state needs to resume the CPU. Quirks are configuration and are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuState {
+    pub regs: [u8; REGS],
+    pub i: u16,
+    pub pc: u16,
+    pub dt: u8,
+    pub st: u8,
+    pub stack: [u16; STACK_SIZE],
+    pub sp: usize,
+    pub rng: u32,
+    pub audio_pattern: Option<[u8; PATTERN_BYTES]>,
+    pub pitch: u8,
 }
 
+//Fixed so that runs are reproducible.
+const DEFAULT_SEED: u32 = 0x2545_F491;
+
 impl Default for Cpu {
     fn default() -> Self {
         Cpu {
@@ -65,6 +108,14 @@ impl Default for Cpu {
             st: 0,
             stack: [0; STACK_SIZE],
             sp: 0,
+            rng: DEFAULT_SEED,
+            seed: DEFAULT_SEED,
+            quirks: Quirks::default(),
+            memory_map: MemoryMap::default(),
+            fonts: FontAddresses::default(),
+            out_of_range: OutOfRange::default(),
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
         }
     }
 }
@@ -74,43 +125,163 @@ impl Cpu {
         Cpu::default()
     }
 
+    //Quirks, the memory map, the out of range behaviour and the seed survive a reset,
+    //everything else starts over.
     pub fn reset(&mut self) {
         *self = Cpu {
-            pc: 0x200,
+            pc: self.memory_map.initial_pc,
+            rng: self.seed,
+            seed: self.seed,
+            quirks: self.quirks,
+            memory_map: self.memory_map,
+            fonts: self.fonts,
+            out_of_range: self.out_of_range,
             ..Default::default()
         }
     }
-    fn handle_beeper(&mut self, audio: &mut AudioSubsystem) {
-        if self.st > 1 && audio.get_status() != AudioStatus::Playing {
-            audio.resume();
-        } else if self.st < 1 {
-            audio.pause();
+
+    pub fn set_memory_map(&mut self, memory_map: MemoryMap) {
+        self.memory_map = memory_map;
+        self.fonts = FontAddresses::new(&memory_map);
+    }
+
+    pub fn set_out_of_range(&mut self, out_of_range: OutOfRange) {
+        self.out_of_range = out_of_range;
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    //Seed for CXNN; zero is not a valid xorshift state and is skipped.
+    pub fn seed(&mut self, seed: u32) {
+        self.seed = if seed == 0 { DEFAULT_SEED } else { seed };
+        self.rng = self.seed;
+    }
+
+    pub fn reg(&self, reg: u8) -> u8 {
+        self.reg_get(reg)
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn st(&self) -> u8 {
+        self.st
+    }
+
+    //Number of return addresses on the stack.
+    pub fn stack_depth(&self) -> usize {
+        self.sp
+    }
+
+    //Beeper is on as long as the sound timer is running.
+    pub fn sound_active(&self) -> bool {
+        self.st > 0
+    }
+
+    //Beeper plus the XO-CHIP pattern and pitch.
+    pub fn sound(&self) -> SoundState {
+        SoundState {
+            on: self.sound_active(),
+            pattern: self.audio_pattern,
+            pitch: self.pitch,
+        }
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            regs: self.regs,
+            i: self.i,
+            pc: self.pc,
+            dt: self.dt,
+            st: self.st,
+            stack: self.stack,
+            sp: self.sp,
+            rng: self.rng,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+        }
+    }
+
+    //Keeps the current quirks, memory map and out of range behaviour.
+    pub fn restore(&mut self, state: &CpuState) {
+        *self = Cpu {
+            regs: state.regs,
+            i: state.i,
+            pc: state.pc,
+            dt: state.dt,
+            st: state.st,
+            stack: state.stack,
+            sp: state.sp.min(STACK_SIZE),
+            rng: state.rng,
+            seed: self.seed,
+            quirks: self.quirks,
+            memory_map: self.memory_map,
+            fonts: self.fonts,
+            out_of_range: self.out_of_range,
+            audio_pattern: state.audio_pattern,
+            pitch: state.pitch,
         }
     }
+
+    //Decrement DT and ST, called at 60Hz.
+    pub fn tick_timers(&mut self) {
+        self.dt_decrement();
+        self.st_decrement();
+    }
+
+    //On a fault PC is left at the offending instruction.
     pub fn step(
         &mut self,
         memory: &mut Memory,
-        display: &mut DisplaySubsystem,
-        input: &mut InputSubsystem,
-        audio: &mut AudioSubsystem,
-    ) {
-        let instruction = memory.read_range(self.pc, 2);
-        println!(
-            "Doing: {:X?} @ pc: {:X?} dt: {} st: {}",
-            instruction, self.pc, self.dt, self.st
-        );
+        pixels: &mut PixelBuffer,
+        keypad: &Keypad,
+    ) -> Result<(), CpuFault> {
+        let pc = self.pc;
+        let result = self.execute(memory, pixels, keypad);
+        if result.is_err() {
+            self.pc = pc;
+        }
+        result
+    }
+
+    fn execute(
+        &mut self,
+        memory: &mut Memory,
+        pixels: &mut PixelBuffer,
+        keypad: &Keypad,
+    ) -> Result<(), CpuFault> {
+        let pc = self.pc;
         self.pc_increment();
-        let (o1, o2, o3, o4) = helper::nibbles(instruction);
-        let reg1 = o2;
-        let reg2 = o3;
-        let address = helper::address(instruction);
-        let value = (instruction[1] & 0xFF) as u8;
+        let instruction = self.read(memory, pc, 2)?;
+        trace!(
+            "{:03X}: {}",
+            pc,
+            disassemble(u16::from_be_bytes([instruction[0], instruction[1]]))
+        );
+        let (o1, o2, o3, o4) = helper::nibbles(&instruction);
+        let address = helper::address(&instruction);
+        let value = instruction[1];
 
         match (o1, o2, o3, o4) {
-            (0x0, 0x0, 0xE, 0x0) => self.clear_screen(display),
-            (0x0, 0x0, 0xE, 0xE) => self.return_from_subroutine(),
+            (0x0, 0x0, 0xE, 0x0) => self.clear_screen(pixels),
+            (0x0, 0x0, 0xE, 0xE) => return self.return_from_subroutine(),
             (0x1, _, _, _) => self.jump_to(address),
-            (0x2, _, _, _) => self.call(address),
+            (0x2, _, _, _) => return self.call(address),
             (0x3, reg, _, _) => self.skip_equal(reg, value),
             (0x4, reg, _, _) => self.skip_not_equal(reg, value),
             (0x5, r1, r2, 0) => self.skip_regs_equal(r1, r2),
@@ -127,31 +298,45 @@ impl Cpu {
             (0x8, r1, r2, 0xE) => self.shift_left(r1, r2),
             (0x9, r1, r2, 0x0) => self.skip_not_regs_equal(r1, r2),
             (0xA, _, _, _) => self.move_i(address),
-            (0xB, _, _, _) => self.jump_with_add(address),
+            (0xB, reg, _, _) => self.jump_with_add(reg, address),
             (0xC, reg, _, _) => self.rnd(reg, value),
-            (0xD, r1, r2, n) => self.draw(r1, r2, n, memory, display),
-            (0xE, reg, 0x9, 0xE) => self.skip_key_pressed(reg, input),
-            (0xE, reg, 0xA, 0x1) => self.skip_key_not_pressed(reg, input),
+            (0xD, r1, r2, n) => return self.draw(r1, r2, n, memory, pixels),
+            (0xE, reg, 0x9, 0xE) => self.skip_key_pressed(reg, keypad),
+            (0xE, reg, 0xA, 0x1) => self.skip_key_not_pressed(reg, keypad),
+            (0xF, 0x0, 0x0, 0x2) => return self.load_audio_pattern(memory),
             (0xF, reg, 0x0, 0x7) => self.get_dt(reg),
-            (0xF, reg, 0x0, 0xA) => self.wait_for_key(reg, input),
+            (0xF, reg, 0x0, 0xA) => self.wait_for_key(reg, keypad),
             (0xF, reg, 0x1, 0x5) => self.set_dt(reg),
             (0xF, reg, 0x1, 0x8) => self.set_st(reg),
             (0xF, reg, 0x1, 0xE) => self.add_to_i(reg),
-            (0xF, reg, 0x2, 0x9) => self.font(reg), // TODO: maybe better function name
-            (0xF, reg, 0x3, 0x3) => self.bcd(reg, memory),
-            (0xF, reg, 0x5, 0x5) => self.store_range(reg, memory),
-            (0xF, reg, 0x6, 0x5) => self.load_range(reg, memory),
-            _ => panic!("WTF: wrong instruction"),
+            (0xF, reg, 0x2, 0x9) => self.font(reg),
+            (0xF, reg, 0x3, 0x0) => return self.big_font(reg),
+            (0xF, reg, 0x3, 0x3) => return self.bcd(reg, memory),
+            (0xF, reg, 0x3, 0xA) => self.set_pitch(reg),
+            (0xF, reg, 0x5, 0x5) => return self.store_range(reg, memory),
+            (0xF, reg, 0x6, 0x5) => return self.load_range(reg, memory),
+            _ => {
+                return UnknownOpcode {
+                    pc: self.instruction_address(),
+                    opcode: u16::from_be_bytes([instruction[0], instruction[1]]),
+                }
+                .fail()
+            }
         }
-        self.dt_decrement();
-        self.st_decrement();
-
-        self.handle_beeper(audio);
+        Ok(())
     }
     //PC DT and ST routines.
     fn pc_increment(&mut self) {
-        self.pc += 2;
-        println!("PC: increment: {:x?}", self.pc);
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    fn pc_decrement(&mut self) {
+        self.pc = self.pc.wrapping_sub(2);
+    }
+
+    //PC is already past the instruction being executed.
+    fn instruction_address(&self) -> u16 {
+        self.pc.wrapping_sub(2)
     }
 
     fn dt_decrement(&mut self) {
@@ -166,18 +351,86 @@ impl Cpu {
         }
     }
 
+    //Memory access. Where address + offset ends up when it is past the end of
+    //memory depends on the out of range behaviour, None means the access is ignored.
+    fn locate(
+        &self,
+        memory: &Memory,
+        address: u16,
+        offset: usize,
+    ) -> Result<Option<u16>, CpuFault> {
+        let target = address as usize + offset;
+        if memory.contains(target) {
+            return Ok(Some(target as u16));
+        }
+        match self.out_of_range {
+            OutOfRange::Wrap => Ok(Some(memory.wrap(target) as u16)),
+            OutOfRange::Fault => MemoryOutOfRange {
+                pc: self.instruction_address(),
+                address: target,
+            }
+            .fail(),
+            OutOfRange::Ignore => {
+                warn!(
+                    "Ignored access to {:X} past the end of memory at {:03X}",
+                    target,
+                    self.instruction_address()
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    //Ignored bytes read as zero.
+    fn read(&self, memory: &Memory, address: u16, count: usize) -> Result<Vec<u8>, CpuFault> {
+        (0..count)
+            .map(|offset| {
+                Ok(match self.locate(memory, address, offset)? {
+                    Some(address) => memory.read_8(address),
+                    None => 0,
+                })
+            })
+            .collect()
+    }
+
+    //Writes to I + offset.
+    fn write(&self, memory: &mut Memory, offset: usize, value: u8) -> Result<(), CpuFault> {
+        if let Some(address) = self.locate(memory, self.i, offset)? {
+            memory.write_8(value, address);
+        }
+        Ok(())
+    }
+
     //Stack push and pop
-    fn stack_push(&mut self, val: u16) {
-        self.sp += 1;
-        assert!(self.sp < STACK_SIZE, "Error! Stack over boundaries");
+    fn stack_push(&mut self, val: u16) -> Result<(), CpuFault> {
+        ensure!(
+            self.sp < STACK_SIZE,
+            StackOverflow {
+                pc: self.instruction_address()
+            }
+        );
         self.stack[self.sp] = val;
+        self.sp += 1;
+        Ok(())
     }
 
-    fn stack_pop(&mut self) -> u16 {
-        let val = self.stack[self.sp];
-        assert!(self.sp >= 1, "Stack error!");
+    fn stack_pop(&mut self) -> Result<u16, CpuFault> {
+        ensure!(
+            self.sp >= 1,
+            StackUnderflow {
+                pc: self.instruction_address()
+            }
+        );
         self.sp -= 1;
-        val
+        Ok(self.stack[self.sp])
+    }
+
+    //xorshift32
+    fn rng_next(&mut self) -> u8 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 24) as u8
     }
 
     //Regs routines
@@ -193,20 +446,17 @@ impl Cpu {
         self.regs[15] = val;
     }
 
-    fn flag_get(&self) -> u8 {
-        self.regs[15]
-    }
-
     //ROUTINES FUNCTIONS
 
     //Clear screen
-    fn clear_screen(&mut self, display: &mut DisplaySubsystem) {
-        display.clear();
+    fn clear_screen(&mut self, pixels: &mut PixelBuffer) {
+        pixels.clear();
     }
 
     //Return from subroutine
-    fn return_from_subroutine(&mut self) {
-        self.pc = self.stack_pop();
+    fn return_from_subroutine(&mut self) -> Result<(), CpuFault> {
+        self.pc = self.stack_pop()?;
+        Ok(())
     }
 
     //Jump to address
@@ -215,9 +465,10 @@ impl Cpu {
     }
 
     //Call subroutine
-    fn call(&mut self, addr: u16) {
-        self.stack_push(self.pc);
+    fn call(&mut self, addr: u16) -> Result<(), CpuFault> {
+        self.stack_push(self.pc)?;
         self.pc = addr;
+        Ok(())
     }
 
     //Skip if equal
@@ -263,18 +514,28 @@ impl Cpu {
     fn or(&mut self, reg1: u8, reg2: u8) {
         let regval = self.reg_get(reg1);
         self.reg_set(reg1, regval | self.reg_get(reg2));
+        self.logic_vf_reset();
     }
 
     //AND values, store result in reg1
     fn and(&mut self, reg1: u8, reg2: u8) {
         let regval = self.reg_get(reg1);
         self.reg_set(reg1, regval & self.reg_get(reg2));
+        self.logic_vf_reset();
     }
 
     //XOR values, store resuilt in reg1
     fn xor(&mut self, reg1: u8, reg2: u8) {
         let regval = self.reg_get(reg1);
         self.reg_set(reg1, regval ^ self.reg_get(reg2));
+        self.logic_vf_reset();
+    }
+
+    //The VIP logic ops go through the ALU which leaves VF cleared.
+    fn logic_vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.flag_set(0);
+        }
     }
 
     //Add reg2 to reg1; if overflows then VF flag is set
@@ -283,11 +544,7 @@ impl Cpu {
         let rv2 = self.reg_get(reg2);
         let result = rv1.overflowing_add(rv2);
         self.reg_set(reg1, result.0);
-        if result.1 == true {
-            self.flag_set(1);
-        } else {
-            self.flag_set(0);
-        }
+        self.flag_set(result.1 as u8);
     }
 
     //Vx = Vx - Vy, set VF = NOT borrow. Set VF if Vx > Vy
@@ -296,19 +553,22 @@ impl Cpu {
         let rv2 = self.reg_get(reg2);
         let result = rv1.overflowing_sub(rv2);
         self.reg_set(reg1, result.0);
-        if result.1 == false {
-            self.flag_set(1);
-        } else {
-            self.flag_set(0);
-        }
+        self.flag_set(!result.1 as u8);
     }
 
     //Shift right. Store less significant bit in VF.
-    // V[reg] = V[reg2] >> 1
+    // V[reg] = V[reg2] >> 1, or V[reg] >> 1 without the shift_vy quirk
     fn shift_right(&mut self, reg: u8, reg2: u8) {
-        let val = self.reg_get(reg2);
-        self.flag_set(val & 1);
+        let val = self.shift_source(reg, reg2);
         self.reg_set(reg, val >> 1);
+        self.flag_set(val & 1);
+    }
+
+    fn shift_source(&self, reg: u8, reg2: u8) -> u8 {
+        match self.quirks.shift_vy {
+            true => self.reg_get(reg2),
+            false => self.reg_get(reg),
+        }
     }
 
     //Set Vx = Vy - Vx, set VF = NOT borrow. Set VF if Vy > Vx
@@ -317,18 +577,14 @@ impl Cpu {
         let rv2 = self.reg_get(reg2);
         let result = rv2.overflowing_sub(rv1);
         self.reg_set(reg1, result.0);
-        if result.1 == false {
-            self.flag_set(1);
-        } else {
-            self.flag_set(0);
-        }
+        self.flag_set(!result.1 as u8);
     }
 
     //Shift left. Most significant bit is stored in VF
     fn shift_left(&mut self, reg: u8, reg2: u8) {
-        let val = self.reg_get(reg2);
-        self.flag_set(val & 0x80); // Get MSB from value
+        let val = self.shift_source(reg, reg2);
         self.reg_set(reg, val << 1);
+        self.flag_set(val >> 7); // Get MSB from value
     }
 
     //Skip next instruction if Vx != Vy.
@@ -343,15 +599,17 @@ impl Cpu {
         self.i = addr;
     }
 
-    //Jump to V0 + addr.
-    fn jump_with_add(&mut self, addr: u16) {
-        let regval = self.reg_get(0) as u16;
+    //Jump to V0 + addr, or VX + addr with the jump_vx quirk.
+    fn jump_with_add(&mut self, reg: u8, addr: u16) {
+        let offset_reg = if self.quirks.jump_vx { reg } else { 0 };
+        let regval = self.reg_get(offset_reg) as u16;
         self.jump_to(addr + regval);
     }
 
     //Load random from 0-255, AND with val and store to V[reg]
     fn rnd(&mut self, reg: u8, val: u8) {
-        self.reg_set(reg, 36 % val); // TODO: Add proper random number.
+        let random = self.rng_next();
+        self.reg_set(reg, random & val);
     }
 
     //Draw [HEIGHT] bytes at (reg1, reg2) position. VF = 1 if there is a collision.
@@ -361,60 +619,61 @@ impl Cpu {
         reg2: u8,
         height: u8,
         mem: &Memory,
-        display: &mut DisplaySubsystem,
-    ) {
-        let mem = mem.read_range(self.i, height as u16);
-        let sprite = Sprite::new(mem);
+        pixels: &mut PixelBuffer,
+    ) -> Result<(), CpuFault> {
+        let bytes = self.read(mem, self.i, height as usize)?;
+        let sprite = Sprite::new(&bytes);
         let column = self.reg_get(reg1) as usize;
         let row = self.reg_get(reg2) as usize;
-        println!("DRAW");
-        let collision = display.draw_test(column, row, sprite);
-        if collision == true {
-            self.flag_set(1);
-        } else {
-            self.flag_set(0);
-        }
+        let collision = pixels.add_sprite(column, row, sprite);
+        self.flag_set(collision as u8);
+        Ok(())
     }
 
     //Skip if key from REG is pressed.
-    fn skip_key_pressed(&mut self, reg: u8, input: &InputSubsystem) {
+    fn skip_key_pressed(&mut self, reg: u8, keypad: &Keypad) {
         let keycode = self.reg_get(reg);
-        KeyboardMapper::map_to_scancode(keycode).and_then::<(), _>(|keycode| {
-            if input.is_key_pressed(keycode) == true {
-                self.pc_increment(); // Key pressed, advance.
-            }
-            Some(()) // Make typesystem happy;
-        });
+        if keypad.is_pressed(keycode) {
+            self.pc_increment(); // Key pressed, advance.
+        }
     }
 
     //Skip if key from reg is NOT pressed
-    fn skip_key_not_pressed(&mut self, reg: u8, input: &InputSubsystem) {
+    fn skip_key_not_pressed(&mut self, reg: u8, keypad: &Keypad) {
         let keycode = self.reg_get(reg);
-        KeyboardMapper::map_to_scancode(keycode).and_then::<(), _>(|keycode| {
-            if input.is_key_pressed(keycode) == false {
-                self.pc_increment(); // Key pressed, advance.
-            }
-            Some(()) // Make typesystem happy;
-        });
+        if !keypad.is_pressed(keycode) {
+            self.pc_increment(); // Key not pressed, advance.
+        }
     }
 
     //Place DT value into REG
     fn get_dt(&mut self, reg: u8) {
-        println!("get_dt: {:X?} {:X?}", reg, self.dt);
         self.reg_set(reg, self.dt);
     }
 
     //Wait for key and load it to reg
-    fn wait_for_key(&mut self, reg: u8, input: &mut InputSubsystem) {
-        unimplemented!()
-        //        let keycode = self.reg_get(reg);
-        //        assert!(keycode < 16, "Keycode value somewhat wrong!");
-        //        input.wait_for_keypress(KeyboardMapper::map_to_scancode(keycode).unwrap());
+    fn wait_for_key(&mut self, reg: u8, keypad: &Keypad) {
+        match keypad.first_pressed() {
+            Some(key) => self.reg_set(reg, key),
+            None => self.pc_decrement(), // No key yet, run this instruction again.
+        }
+    }
+
+    //XO-CHIP: load the 16 byte audio pattern from I.
+    fn load_audio_pattern(&mut self, memory: &Memory) -> Result<(), CpuFault> {
+        let mut pattern = [0; PATTERN_BYTES];
+        pattern.copy_from_slice(&self.read(memory, self.i, PATTERN_BYTES)?);
+        self.audio_pattern = Some(pattern);
+        Ok(())
+    }
+
+    //XO-CHIP: pattern playback rate from V[REG].
+    fn set_pitch(&mut self, reg: u8) {
+        self.pitch = self.reg_get(reg);
     }
 
     //Set DT value from REG
     fn set_dt(&mut self, reg: u8) {
-        println!("set_dt: val: {:X?}", self.reg_get(reg));
         self.dt = self.reg_get(reg);
     }
 
@@ -425,43 +684,607 @@ impl Cpu {
 
     //Add I to V[REG] andr store it in I.
     fn add_to_i(&mut self, reg: u8) {
-        self.i += self.reg_get(reg) as u16;
+        self.i = self.i.wrapping_add(self.reg_get(reg) as u16);
     }
 
     //Set I to location of sprite digit from V[REG]
     fn font(&mut self, reg: u8) {
-        //Font is 5 bytes; address of the font sprite is font number * 5
-        //TODO: CPU should not calculate this address, fix it.
-        self.i = self.reg_get(reg) as u16 * 5;
+        self.i = self.fonts.small_glyph(self.reg_get(reg));
+    }
+
+    //Set I to location of the big digit from V[REG], on platforms that have a big font.
+    fn big_font(&mut self, reg: u8) -> Result<(), CpuFault> {
+        match self.fonts.big_glyph(self.reg_get(reg)) {
+            Some(address) => {
+                self.i = address;
+                Ok(())
+            }
+            None => UnknownOpcode {
+                pc: self.instruction_address(),
+                opcode: 0xF030 | (reg as u16) << 8,
+            }
+            .fail(),
+        }
     }
 
     //Store three digits in I I+1 I+2
-    fn bcd(&mut self, reg: u8, memory: &mut Memory) {
-        let mut value = self.reg_get(reg);
-        memory.write_8( ( value ) / 100, self.i);
-        memory.write_8( ( value / 10 ) % 10, self.i + 1);
-        memory.write_8( ( value ) % 10, self.i + 2);
+    fn bcd(&mut self, reg: u8, memory: &mut Memory) -> Result<(), CpuFault> {
+        let value = self.reg_get(reg);
+        self.write(memory, 0, value / 100)?;
+        self.write(memory, 1, (value / 10) % 10)?;
+        self.write(memory, 2, value % 10)
     }
 
     //Store all registers from V[0] to V[REG] starting from I.
-    fn store_range(&mut self, reg: u8, memory: &mut Memory) {
+    fn store_range(&mut self, reg: u8, memory: &mut Memory) -> Result<(), CpuFault> {
         //For 0 to reg - read all regs and store in memory starting from I.
         for i in 0..=reg {
             let regval = self.reg_get(i);
-            self.i += i as u16;
-            let addr = self.i;
-            memory.write_8(regval, addr);
+            self.write(memory, i as usize, regval)?;
         }
-        self.i += 1;
+        self.memory_increment(reg);
+        Ok(())
     }
 
     //Load values to registers from V[0] to V[REG] starting from I.
-    fn load_range(&mut self, reg: u8, memory: &Memory) {
-        for i in 0..=reg {
-            self.i += i as u16;
-            let memval = memory.read_8(self.i);
-            self.reg_set(i, memval);
+    fn load_range(&mut self, reg: u8, memory: &Memory) -> Result<(), CpuFault> {
+        let values = self.read(memory, self.i, reg as usize + 1)?;
+        for (i, memval) in values.into_iter().enumerate() {
+            self.reg_set(i as u8, memval);
+        }
+        self.memory_increment(reg);
+        Ok(())
+    }
+
+    //Where I ends up after FX55/FX65 depends on the interpreter.
+    fn memory_increment(&mut self, reg: u8) {
+        match self.quirks.memory_increment {
+            MemoryIncrement::XPlusOne => self.i = self.i.wrapping_add(reg as u16 + 1),
+            MemoryIncrement::X => self.i = self.i.wrapping_add(reg as u16),
+            MemoryIncrement::Unchanged => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cpu::{Cpu, CpuFault};
+    use crate::framebuffer::PixelBuffer;
+    use crate::keypad::Keypad;
+    use crate::mem::{Memory, OutOfRange};
+    use crate::memmap::MemoryMap;
+    use crate::quirks::{MemoryIncrement, Quirks};
+
+    //Cpu with its surroundings, program loaded at 0x200.
+    struct Bench {
+        cpu: Cpu,
+        memory: Memory,
+        pixels: PixelBuffer,
+        keypad: Keypad,
+    }
+
+    impl Bench {
+        fn new(program: &[u8]) -> Bench {
+            Bench::with_quirks(program, Quirks::vip())
+        }
+
+        fn with_quirks(program: &[u8], quirks: Quirks) -> Bench {
+            let mut memory = Memory::new();
+            for (i, byte) in program.iter().enumerate() {
+                memory.write_8(*byte, 0x200 + i as u16);
+            }
+            let mut cpu = Cpu::new();
+            cpu.set_quirks(quirks);
+            cpu.reset();
+            Bench {
+                cpu,
+                memory,
+                pixels: PixelBuffer::default(),
+                keypad: Keypad::new(),
+            }
+        }
+
+        fn step(&mut self, steps: usize) -> &mut Bench {
+            for _ in 0..steps {
+                self.try_step().unwrap();
+            }
+            self
+        }
+
+        fn try_step(&mut self) -> Result<(), CpuFault> {
+            self.cpu
+                .step(&mut self.memory, &mut self.pixels, &self.keypad)
+        }
+
+        fn v(&self, reg: u8) -> u8 {
+            self.cpu.reg(reg)
+        }
+    }
+
+    //Sets VX = a, VY = b and runs one 8XY? instruction on them.
+    fn alu(x: u8, y: u8, a: u8, b: u8, op: u8) -> Bench {
+        let quirks = Quirks {
+            shift_vy: false,
+            ..Quirks::vip()
+        };
+        let mut bench =
+            Bench::with_quirks(&[0x60 | x, a, 0x60 | y, b, 0x80 | x, (y << 4) | op], quirks);
+        bench.step(3);
+        bench
+    }
+
+    #[test]
+    fn clear_screen_test() {
+        let mut bench = Bench::new(&[0xA2, 0x06, 0xD0, 0x01, 0x00, 0xE0, 0xFF]);
+        bench.step(2);
+        assert!(bench.pixels.get(0, 0));
+        bench.step(1);
+        assert!(!bench.pixels.get(0, 0));
+    }
+
+    #[test]
+    fn jump_test() {
+        let mut bench = Bench::new(&[0x13, 0x45]);
+        bench.step(1);
+        assert_eq!(bench.cpu.pc(), 0x345);
+    }
+
+    #[test]
+    fn call_and_return_test() {
+        // 200: call 206, 202: V0 = 1, 204: jump 204, 206: V1 = 2, 208: return
+        let mut bench = Bench::new(&[0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x00, 0xEE]);
+        bench.step(1);
+        assert_eq!(bench.cpu.pc(), 0x206);
+        assert_eq!(bench.cpu.stack_depth(), 1);
+        bench.step(2);
+        assert_eq!(bench.cpu.pc(), 0x202);
+        assert_eq!(bench.cpu.stack_depth(), 0);
+        bench.step(1);
+        assert_eq!((bench.v(0), bench.v(1)), (1, 2));
+    }
+
+    #[test]
+    fn stack_full_depth_test() {
+        // Every call goes to the next instruction, 16 deep.
+        let mut program = Vec::new();
+        for i in 0..16u16 {
+            let target = 0x202 + i * 2;
+            program.extend_from_slice(&[0x20 | (target >> 8) as u8, target as u8]);
+        }
+        program.extend_from_slice(&[0x00, 0xEE]);
+        let mut bench = Bench::new(&program);
+        bench.step(16);
+        assert_eq!(bench.cpu.stack_depth(), 16);
+        bench.step(1);
+        assert_eq!(bench.cpu.stack_depth(), 15);
+        assert_eq!(bench.cpu.pc(), 0x220);
+    }
+
+    #[test]
+    fn stack_overflow_test() {
+        // Calls itself forever.
+        let mut bench = Bench::new(&[0x22, 0x00]);
+        bench.step(16);
+        assert_eq!(bench.try_step(), Err(CpuFault::StackOverflow { pc: 0x200 }));
+        assert_eq!(bench.cpu.pc(), 0x200);
+        assert_eq!(bench.cpu.stack_depth(), 16);
+    }
+
+    #[test]
+    fn stack_underflow_test() {
+        let mut bench = Bench::new(&[0x60, 0x01, 0x00, 0xEE]);
+        bench.step(1);
+        assert_eq!(
+            bench.try_step(),
+            Err(CpuFault::StackUnderflow { pc: 0x202 })
+        );
+        assert_eq!(bench.cpu.pc(), 0x202);
+    }
+
+    #[test]
+    fn unknown_opcode_test() {
+        let mut bench = Bench::new(&[0x60, 0x01, 0xFF, 0xFF]);
+        bench.step(1);
+        let fault = bench.try_step().unwrap_err();
+        assert_eq!(
+            fault,
+            CpuFault::UnknownOpcode {
+                pc: 0x202,
+                opcode: 0xFFFF
+            }
+        );
+        assert_eq!(fault.to_string(), "Unknown opcode FFFF at 202");
+        assert_eq!(bench.cpu.pc(), 0x202);
+    }
+
+    #[test]
+    fn skip_equal_test() {
+        let mut bench = Bench::new(&[0x60, 0x05, 0x30, 0x05]);
+        assert_eq!(bench.step(2).cpu.pc(), 0x206);
+        let mut bench = Bench::new(&[0x60, 0x05, 0x30, 0x06]);
+        assert_eq!(bench.step(2).cpu.pc(), 0x204);
+    }
+
+    #[test]
+    fn skip_not_equal_test() {
+        let mut bench = Bench::new(&[0x60, 0x05, 0x40, 0x05]);
+        assert_eq!(bench.step(2).cpu.pc(), 0x204);
+        let mut bench = Bench::new(&[0x60, 0x05, 0x40, 0x06]);
+        assert_eq!(bench.step(2).cpu.pc(), 0x206);
+    }
+
+    #[test]
+    fn skip_regs_equal_test() {
+        let mut bench = Bench::new(&[0x60, 0x05, 0x61, 0x05, 0x50, 0x10]);
+        assert_eq!(bench.step(3).cpu.pc(), 0x208);
+        let mut bench = Bench::new(&[0x60, 0x05, 0x61, 0x06, 0x50, 0x10]);
+        assert_eq!(bench.step(3).cpu.pc(), 0x206);
+    }
+
+    #[test]
+    fn skip_regs_not_equal_test() {
+        let mut bench = Bench::new(&[0x60, 0x05, 0x61, 0x05, 0x90, 0x10]);
+        assert_eq!(bench.step(3).cpu.pc(), 0x206);
+        let mut bench = Bench::new(&[0x60, 0x05, 0x61, 0x06, 0x90, 0x10]);
+        assert_eq!(bench.step(3).cpu.pc(), 0x208);
+    }
+
+    #[test]
+    fn mov_test() {
+        let mut bench = Bench::new(&[0x6A, 0x42]);
+        assert_eq!(bench.step(1).v(0xA), 0x42);
+    }
+
+    #[test]
+    fn add_test() {
+        // 7XNN wraps around and leaves VF alone.
+        let mut bench = Bench::new(&[0x6F, 0x07, 0x60, 0xFF, 0x70, 0x02]);
+        bench.step(3);
+        assert_eq!(bench.v(0), 0x01);
+        assert_eq!(bench.v(0xF), 0x07);
+    }
+
+    #[test]
+    fn mov_regs_test() {
+        assert_eq!(alu(0, 1, 0x00, 0x33, 0x0).v(0), 0x33);
+    }
+
+    #[test]
+    fn logic_test() {
+        assert_eq!(alu(0, 1, 0b1100, 0b1010, 0x1).v(0), 0b1110);
+        assert_eq!(alu(0, 1, 0b1100, 0b1010, 0x2).v(0), 0b1000);
+        assert_eq!(alu(0, 1, 0b1100, 0b1010, 0x3).v(0), 0b0110);
+    }
+
+    #[test]
+    fn logic_vf_reset_test() {
+        // VF = 1, V0 |= V1
+        let program = &[0x6F, 0x01, 0x80, 0x11];
+        let mut bench = Bench::with_quirks(program, Quirks::vip());
+        assert_eq!(bench.step(2).v(0xF), 0);
+        let mut bench = Bench::with_quirks(program, Quirks::schip());
+        assert_eq!(bench.step(2).v(0xF), 1);
+    }
+
+    #[test]
+    fn add_regs_test() {
+        let bench = alu(0, 1, 0xF0, 0x20, 0x4);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x10, 1));
+        let bench = alu(0, 1, 0x10, 0x20, 0x4);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x30, 0));
+        // Flag wins over the result when VF is the target.
+        let bench = alu(0xF, 1, 0xF0, 0x20, 0x4);
+        assert_eq!(bench.v(0xF), 1);
+        let bench = alu(0xF, 1, 0x10, 0x20, 0x4);
+        assert_eq!(bench.v(0xF), 0);
+    }
+
+    #[test]
+    fn sub_regs_test() {
+        let bench = alu(0, 1, 0x30, 0x10, 0x5);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x20, 1));
+        let bench = alu(0, 1, 0x10, 0x30, 0x5);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0xE0, 0));
+        let bench = alu(0, 1, 0x10, 0x10, 0x5);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x00, 1));
+        let bench = alu(0xF, 1, 0x10, 0x30, 0x5);
+        assert_eq!(bench.v(0xF), 0);
+    }
+
+    #[test]
+    fn sub_regs_2_test() {
+        let bench = alu(0, 1, 0x10, 0x30, 0x7);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x20, 1));
+        let bench = alu(0, 1, 0x30, 0x10, 0x7);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0xE0, 0));
+        let bench = alu(0, 1, 0x10, 0x10, 0x7);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x00, 1));
+        let bench = alu(0xF, 1, 0x10, 0x30, 0x7);
+        assert_eq!(bench.v(0xF), 1);
+    }
+
+    #[test]
+    fn shift_right_test() {
+        let bench = alu(0, 1, 0x05, 0x00, 0x6);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x02, 1));
+        let bench = alu(0, 1, 0x04, 0x00, 0x6);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x02, 0));
+        let bench = alu(0xF, 1, 0x05, 0x00, 0x6);
+        assert_eq!(bench.v(0xF), 1);
+    }
+
+    #[test]
+    fn shift_left_test() {
+        let bench = alu(0, 1, 0x81, 0x00, 0xE);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x02, 1));
+        let bench = alu(0, 1, 0x41, 0x00, 0xE);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x82, 0));
+        let bench = alu(0xF, 1, 0x81, 0x00, 0xE);
+        assert_eq!(bench.v(0xF), 1);
+    }
+
+    #[test]
+    fn shift_vy_quirk_test() {
+        // V0 = 0x10, V1 = 0x03, V0 = V1 >> 1 or V0 >> 1
+        let program = &[0x60, 0x10, 0x61, 0x03, 0x80, 0x16];
+        let mut bench = Bench::with_quirks(program, Quirks::vip());
+        bench.step(3);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x01, 1));
+        let mut bench = Bench::with_quirks(program, Quirks::schip());
+        bench.step(3);
+        assert_eq!((bench.v(0), bench.v(0xF)), (0x08, 0));
+    }
+
+    #[test]
+    fn move_i_test() {
+        let mut bench = Bench::new(&[0xA1, 0x23]);
+        assert_eq!(bench.step(1).cpu.i(), 0x123);
+    }
+
+    #[test]
+    fn jump_with_add_test() {
+        // V0 = 2, V3 = 4, jump to 0x300 + V0 or V3
+        let program = &[0x60, 0x02, 0x63, 0x04, 0xB3, 0x00];
+        let mut bench = Bench::with_quirks(program, Quirks::vip());
+        assert_eq!(bench.step(3).cpu.pc(), 0x302);
+        let mut bench = Bench::with_quirks(program, Quirks::schip());
+        assert_eq!(bench.step(3).cpu.pc(), 0x304);
+    }
+
+    #[test]
+    fn rnd_test() {
+        let mut program = Vec::new();
+        for _ in 0..32 {
+            program.extend_from_slice(&[0xC0, 0x0F]);
+        }
+        let mut bench = Bench::new(&program);
+        let mut seen = Vec::new();
+        for _ in 0..32 {
+            let value = bench.step(1).v(0);
+            assert_eq!(value & 0xF0, 0);
+            seen.push(value);
+        }
+        seen.sort();
+        seen.dedup();
+        assert!(seen.len() > 4);
+    }
+
+    #[test]
+    fn draw_test() {
+        // Draw the 0 glyph at (V0, V1) = (2, 3) twice.
+        let mut bench = Bench::new(&[
+            0x60, 0x02, 0x61, 0x03, 0xA2, 0x0A, 0xD0, 0x15, 0xD0, 0x15, 0xF0, 0x90, 0x90, 0x90,
+            0xF0,
+        ]);
+        bench.step(4);
+        assert!(bench.pixels.get(2, 3));
+        assert!(bench.pixels.get(5, 7));
+        assert!(!bench.pixels.get(3, 4));
+        assert_eq!(bench.v(0xF), 0);
+        bench.step(1);
+        assert!(!bench.pixels.get(2, 3));
+        assert_eq!(bench.v(0xF), 1);
+    }
+
+    #[test]
+    fn skip_key_test() {
+        let program = &[0x60, 0x07, 0xE0, 0x9E, 0xE0, 0xA1];
+        let mut bench = Bench::new(program);
+        bench.keypad.press(7);
+        assert_eq!(bench.step(2).cpu.pc(), 0x206);
+        let mut bench = Bench::new(program);
+        assert_eq!(bench.step(2).cpu.pc(), 0x204);
+        assert_eq!(bench.step(1).cpu.pc(), 0x208);
+    }
+
+    #[test]
+    fn wait_for_key_test() {
+        let mut bench = Bench::new(&[0xF3, 0x0A]);
+        assert_eq!(bench.step(3).cpu.pc(), 0x200);
+        bench.keypad.press(0xB);
+        assert_eq!(bench.step(1).cpu.pc(), 0x202);
+        assert_eq!(bench.v(3), 0xB);
+    }
+
+    #[test]
+    fn delay_timer_test() {
+        let mut bench = Bench::new(&[0x60, 0x03, 0xF0, 0x15, 0xF1, 0x07]);
+        bench.step(2);
+        bench.cpu.tick_timers();
+        bench.step(1);
+        assert_eq!(bench.v(1), 2);
+        for _ in 0..5 {
+            bench.cpu.tick_timers();
         }
-        self.i += 1;
+        assert_eq!(bench.cpu.dt(), 0);
+    }
+
+    #[test]
+    fn sound_timer_test() {
+        let mut bench = Bench::new(&[0x60, 0x02, 0xF0, 0x18]);
+        bench.step(2);
+        assert_eq!(bench.cpu.st(), 2);
+        assert!(bench.cpu.sound_active());
+        bench.cpu.tick_timers();
+        assert!(bench.cpu.sound_active());
+        bench.cpu.tick_timers();
+        assert!(!bench.cpu.sound_active());
+        bench.cpu.tick_timers();
+        assert_eq!(bench.cpu.st(), 0);
+    }
+
+    #[test]
+    fn add_to_i_test() {
+        let mut bench = Bench::new(&[0xA1, 0x00, 0x60, 0x22, 0xF0, 0x1E]);
+        assert_eq!(bench.step(3).cpu.i(), 0x122);
+    }
+
+    #[test]
+    fn font_test() {
+        let mut bench = Bench::new(&[0x60, 0x0A, 0xF0, 0x29]);
+        assert_eq!(bench.step(2).cpu.i(), 0x0A * 5);
+    }
+
+    #[test]
+    fn bcd_test() {
+        for &(value, digits) in &[(255u8, [2u8, 5, 5]), (7, [0, 0, 7]), (100, [1, 0, 0])] {
+            let mut bench = Bench::new(&[0x60, value, 0xA3, 0x00, 0xF0, 0x33]);
+            bench.step(3);
+            assert_eq!(bench.memory.read_range(0x300, 3), &digits);
+            assert_eq!(bench.cpu.i(), 0x300);
+        }
+    }
+
+    #[test]
+    fn store_range_test() {
+        let program = &[0x60, 0x11, 0x61, 0x22, 0x62, 0x33, 0xA3, 0x00, 0xF2, 0x55];
+        for &(increment, i) in &[
+            (MemoryIncrement::XPlusOne, 0x303),
+            (MemoryIncrement::X, 0x302),
+            (MemoryIncrement::Unchanged, 0x300),
+        ] {
+            let quirks = Quirks {
+                memory_increment: increment,
+                ..Quirks::vip()
+            };
+            let mut bench = Bench::with_quirks(program, quirks);
+            bench.step(5);
+            assert_eq!(bench.memory.read_range(0x300, 4), &[0x11, 0x22, 0x33, 0x00]);
+            assert_eq!(bench.cpu.i(), i);
+        }
+    }
+
+    #[test]
+    fn load_range_test() {
+        // Loads V0-V2 from the last three bytes of the program.
+        let program = &[0xA2, 0x06, 0xF2, 0x65, 0x12, 0x04, 0x11, 0x22, 0x33, 0x44];
+        for &(increment, i) in &[
+            (MemoryIncrement::XPlusOne, 0x209),
+            (MemoryIncrement::X, 0x208),
+            (MemoryIncrement::Unchanged, 0x206),
+        ] {
+            let quirks = Quirks {
+                memory_increment: increment,
+                ..Quirks::vip()
+            };
+            let mut bench = Bench::with_quirks(program, quirks);
+            bench.step(2);
+            assert_eq!(
+                (bench.v(0), bench.v(1), bench.v(2), bench.v(3)),
+                (0x11, 0x22, 0x33, 0)
+            );
+            assert_eq!(bench.cpu.i(), i);
+        }
+    }
+
+    #[test]
+    fn reset_keeps_quirks_test() {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(Quirks::schip());
+        cpu.reset();
+        assert_eq!(cpu.quirks(), Quirks::schip());
+        assert_eq!(cpu.pc(), 0x200);
+    }
+
+    #[test]
+    fn memory_map_test() {
+        let mut cpu = Cpu::new();
+        cpu.set_memory_map(MemoryMap::eti660());
+        cpu.reset();
+        assert_eq!(cpu.pc(), 0x600);
+    }
+
+    #[test]
+    fn big_font_test() {
+        let program = [0x60, 0x03, 0xF0, 0x30];
+        let mut bench = Bench::new(&program);
+        bench.step(1);
+        assert_eq!(
+            bench.try_step(),
+            Err(CpuFault::UnknownOpcode {
+                pc: 0x202,
+                opcode: 0xF030
+            })
+        );
+        let mut bench = Bench::new(&program);
+        bench.cpu.set_memory_map(MemoryMap::schip());
+        assert_eq!(bench.step(2).cpu.i(), 0x50 + 30);
+    }
+
+    #[test]
+    fn audio_pattern_test() {
+        // I = 0x208, load pattern, V0 = 0x70, pitch = V0, then the pattern bytes.
+        let mut program = vec![0xA2, 0x08, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A];
+        program.extend((0..16).map(|b| b as u8 * 0x11));
+        let mut bench = Bench::new(&program);
+        assert_eq!(bench.cpu.sound().pattern, None);
+        bench.step(2);
+        let pattern = bench.cpu.sound().pattern.unwrap();
+        assert_eq!(pattern[1], 0x11);
+        assert_eq!(pattern[15], 0xFF);
+        assert_eq!(bench.cpu.sound().pitch, 64);
+        bench.step(2);
+        assert_eq!(bench.cpu.sound().pitch, 0x70);
+    }
+
+    #[test]
+    fn out_of_range_test() {
+        // I = 0xFFE, store V0-V2 across the end of memory, then draw 2 rows from 0xFFF.
+        let program = &[
+            0xAF, 0xFE, 0x60, 0x11, 0x61, 0x22, 0x62, 0x33, 0xF2, 0x55, 0xAF, 0xFF, 0xD0, 0x02,
+        ];
+        let bench_with = |out_of_range| {
+            let mut bench = Bench::new(program);
+            bench.cpu.set_out_of_range(out_of_range);
+            bench.cpu.reset();
+            bench
+        };
+
+        let mut bench = bench_with(OutOfRange::Wrap);
+        bench.step(5);
+        assert_eq!(bench.memory.read_range(0xFFE, 2), &[0x11, 0x22]);
+        assert_eq!(bench.memory.read_8(0x000), 0x33);
+        assert_eq!(bench.cpu.i(), 0x1001);
+        bench.step(2);
+        // 0x22 then 0x33 at (V0, V0).
+        assert!(bench.pixels.get(0x13, 0x11));
+        assert!(bench.pixels.get(0x13, 0x12) && bench.pixels.get(0x14, 0x12));
+
+        let mut bench = bench_with(OutOfRange::Fault);
+        bench.step(4);
+        assert_eq!(
+            bench.try_step(),
+            Err(CpuFault::MemoryOutOfRange {
+                pc: 0x208,
+                address: 0x1000
+            })
+        );
+        assert_eq!(bench.cpu.pc(), 0x208);
+
+        let mut bench = bench_with(OutOfRange::Ignore);
+        bench.step(7);
+        assert_eq!(bench.memory.read_8(0x000), 0x00);
+        assert!(bench.pixels.get(0x13, 0x11));
+        assert!(!bench.pixels.get(0x13, 0x12));
     }
 }
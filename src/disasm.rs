@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+//Label names for addresses, from assembling a program's source.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    //An address with several labels keeps the first one.
+    pub fn insert(&mut self, address: u16, name: &str) {
+        self.names
+            .entry(address)
+            .or_insert_with(|| name.to_string());
+    }
+
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+//Mnemonics follow Cowgod's CHIP-8 reference, plus the XO-CHIP audio opcodes.
+pub fn disassemble(opcode: u16) -> String {
+    disassemble_with(opcode, &Symbols::default())
+}
+
+//Like disassemble, with label names in place of the addresses that have one.
+pub fn disassemble_with(opcode: u16, symbols: &Symbols) -> String {
+    let nibbles = (
+        (opcode >> 12) as u8,
+        (opcode >> 8 & 0xF) as u8,
+        (opcode >> 4 & 0xF) as u8,
+        (opcode & 0xF) as u8,
+    );
+    let x = nibbles.1;
+    let y = nibbles.2;
+    let nn = opcode & 0xFF;
+    let nnn = match symbols.name(opcode & 0xFFF) {
+        Some(name) => name.to_string(),
+        None => format!("{:03X}", opcode & 0xFFF),
+    };
+
+    match nibbles {
+        (0x0, 0x0, 0xE, 0x0) => "CLS".to_string(),
+        (0x0, 0x0, 0xE, 0xE) => "RET".to_string(),
+        (0x1, _, _, _) => format!("JP {}", nnn),
+        (0x2, _, _, _) => format!("CALL {}", nnn),
+        (0x3, _, _, _) => format!("SE V{:X}, {:02X}", x, nn),
+        (0x4, _, _, _) => format!("SNE V{:X}, {:02X}", x, nn),
+        (0x5, _, _, 0x0) => format!("SE V{:X}, V{:X}", x, y),
+        (0x6, _, _, _) => format!("LD V{:X}, {:02X}", x, nn),
+        (0x7, _, _, _) => format!("ADD V{:X}, {:02X}", x, nn),
+        (0x8, _, _, 0x0) => format!("LD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x1) => format!("OR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x2) => format!("AND V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x3) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x4) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x5) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x6) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0x7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x8, _, _, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x9, _, _, 0x0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, _, _, _) => format!("LD I, {}", nnn),
+        (0xB, _, _, _) => format!("JP V0, {}", nnn),
+        (0xC, _, _, _) => format!("RND V{:X}, {:02X}", x, nn),
+        (0xD, _, _, n) => format!("DRW V{:X}, V{:X}, {:X}", x, y, n),
+        (0xE, _, 0x9, 0xE) => format!("SKP V{:X}", x),
+        (0xE, _, 0xA, 0x1) => format!("SKNP V{:X}", x),
+        (0xF, 0x0, 0x0, 0x2) => "AUDIO".to_string(),
+        (0xF, _, 0x0, 0x7) => format!("LD V{:X}, DT", x),
+        (0xF, _, 0x0, 0xA) => format!("LD V{:X}, K", x),
+        (0xF, _, 0x1, 0x5) => format!("LD DT, V{:X}", x),
+        (0xF, _, 0x1, 0x8) => format!("LD ST, V{:X}", x),
+        (0xF, _, 0x1, 0xE) => format!("ADD I, V{:X}", x),
+        (0xF, _, 0x2, 0x9) => format!("LD F, V{:X}", x),
+        (0xF, _, 0x3, 0x0) => format!("LD HF, V{:X}", x),
+        (0xF, _, 0x3, 0x3) => format!("LD B, V{:X}", x),
+        (0xF, _, 0x3, 0xA) => format!("PITCH V{:X}", x),
+        (0xF, _, 0x5, 0x5) => format!("LD [I], V{:X}", x),
+        (0xF, _, 0x6, 0x5) => format!("LD V{:X}, [I]", x),
+        _ => format!("DW {:04X}", opcode),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::disasm::{disassemble, disassemble_with, Symbols};
+
+    #[test]
+    fn disassemble_test() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x1234), "JP 234");
+        assert_eq!(disassemble(0x6A0F), "LD VA, 0F");
+        assert_eq!(disassemble(0x8126), "SHR V1, V2");
+        assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+        assert_eq!(disassemble(0xF355), "LD [I], V3");
+        assert_eq!(disassemble(0xF002), "AUDIO");
+    }
+
+    #[test]
+    fn symbols_test() {
+        let mut symbols = Symbols::default();
+        symbols.insert(0x202, "main");
+        symbols.insert(0x202, "start");
+        symbols.insert(0x300, "sprite");
+        assert_eq!(disassemble_with(0x1202, &symbols), "JP main");
+        assert_eq!(disassemble_with(0xA300, &symbols), "LD I, sprite");
+        assert_eq!(disassemble_with(0x2204, &symbols), "CALL 204");
+        assert_eq!(disassemble_with(0x6202, &symbols), "LD V2, 02");
+    }
+
+    #[test]
+    fn unknown_opcode_test() {
+        assert_eq!(disassemble(0x5121), "DW 5121");
+        assert_eq!(disassemble(0xFFFF), "DW FFFF");
+    }
+}
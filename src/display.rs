@@ -1,189 +1,194 @@
-#![feature(fixed_size_array)]
-
-use crate::utils::BitVec;
-use sdl2::pixels::Color;
+use crate::osd::Osd;
+use chip8forever::framebuffer::{PixelBuffer, COLUMNS, ROWS};
+use chip8forever::palette::Palette;
+use chip8forever::persistence::Persistence;
+use log::warn;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
-use sdl2::video::Window;
+use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+use sdl2::video::{FullscreenType, WindowContext};
 use sdl2::Sdl;
-use std::fmt::{Debug, Error, Formatter};
+use std::str::FromStr;
+
+//How the pixel buffer is stretched to fill the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    //Only whole multiples of the screen size, sharp pixels.
+    Integer,
+    //Any factor that fits the window, filtered.
+    Smooth,
+}
+
+impl FromStr for Scaling {
+    type Err = String;
 
-const PIXEL_SIZE: u32 = 10;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "integer" => Ok(Scaling::Integer),
+            "smooth" => Ok(Scaling::Smooth),
+            _ => Err(format!("Unknown scaling mode {}, use integer or smooth", s)),
+        }
+    }
+}
 
 pub struct DisplaySubsystem {
     canvas: WindowCanvas,
-    color: Color,
-    pixel_buffer: PixelBuffer,
+    texture_creator: TextureCreator<WindowContext>,
+    //Streaming texture the size of the pixel buffer, made on the first update.
+    texture: Option<Texture>,
+    palette: Palette,
+    persistence: Persistence,
+    scaling: Scaling,
 }
 
 impl DisplaySubsystem {
-    pub fn new(context: &Sdl, title: &str, width: u32, height: u32) -> DisplaySubsystem {
-        let video_subsystem = context.video().unwrap();
+    pub fn new(
+        context: &Sdl,
+        title: &str,
+        width: u32,
+        height: u32,
+        scaling: Scaling,
+        palette: Palette,
+    ) -> Result<DisplaySubsystem, String> {
+        let video_subsystem = context.video()?;
         let mut window = video_subsystem
             .window(title, width, height)
             .position_centered()
+            .resizable()
             .build()
-            .unwrap();
-
-        let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+            .map_err(|e| e.to_string())?;
+        let _ = window.set_minimum_size(COLUMNS as u32, ROWS as u32);
+
+        // Filtering is picked up by every texture created after this call.
+        let quality = match scaling {
+            Scaling::Integer => "nearest",
+            Scaling::Smooth => "linear",
+        };
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", quality);
+
+        let canvas = window
+            .into_canvas()
+            .present_vsync()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let texture_creator = canvas.texture_creator();
 
-        DisplaySubsystem {
+        Ok(DisplaySubsystem {
             canvas,
-            color: Color::RGB(0, 0, 0),
-            pixel_buffer: PixelBuffer::new(
-                (width / PIXEL_SIZE) as usize,
-                (height / PIXEL_SIZE) as usize,
-            ),
-        }
-    }
-
-    //TODO: BUG PixelBuffer is not cleared here!
-    pub fn clear(&mut self) {
-        self.pixel_buffer.clear();
-        self.canvas.clear();
-    }
-    // Draw current pixel buffer to the screen!
-    pub fn update(&mut self) {
-        self.pixel_buffer.draw_on_canvas(&mut self.canvas);
-        self.canvas.present();
+            texture_creator,
+            texture: None,
+            palette,
+            persistence: Persistence::new(0.0),
+            scaling,
+        })
     }
 
-    pub fn draw(&mut self, column: usize, row: usize, sprite: Sprite) -> bool {
-        let collision = self.pixel_buffer.add_sprite(column, row, sprite);
-        collision
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let next = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        if let Err(e) = window.set_fullscreen(next) {
+            warn!("Could not toggle fullscreen: {}", e);
+        }
     }
 
-    pub fn draw_test(&mut self, column: usize, row: usize, sprite: Sprite) -> bool {
+    // Draw current pixel buffer to the screen, with the overlay on top.
+    pub fn update(&mut self, pixels: &PixelBuffer, osd: &mut Osd) -> Result<(), String> {
+        let columns = pixels.columns() as u32;
+        let rows = pixels.rows() as u32;
+        self.persistence.update(pixels);
+        let image = self.persistence.render(&self.palette, 1);
+        let texture = self.texture(columns, rows)?;
+        texture
+            .update(None, &image.data, image.width as usize * 3)
+            .map_err(|e| e.to_string())?;
+
+        let output = self.canvas.output_size()?;
+        let target = letterbox(output, (columns, rows), self.scaling);
+        let (r, g, b) = self.palette.background;
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
         self.canvas.clear();
-        self.set_color(Color::RGB(255, 255, 255));
-        let collision = self.pixel_buffer.add_sprite(column, row, sprite);
-        self.pixel_buffer.draw_on_canvas(&mut self.canvas);
-        self.canvas.present();
-        self.set_color(Color::RGB(0, 0, 0));
-        collision
-    }
-
-    pub fn set_color(&mut self, color: Color) {
-        self.color = color;
-        self.canvas.set_draw_color(self.color);
-    }
-}
-
-//pixelbuffer is arrray representing what is shown on the screen.
-struct PixelBuffer {
-    pixels: Vec<Vec<bool>>,
-    columns: usize,
-    rows: usize,
-}
-
-impl PixelBuffer {
-    fn new(columns: usize, rows: usize) -> PixelBuffer {
-        PixelBuffer {
-            pixels: vec![vec![false; columns]; rows],
-            columns,
-            rows,
+        if let Some(texture) = &self.texture {
+            self.canvas.copy(texture, None, target)?;
         }
-    }
-    fn add_sprite(&mut self, column: usize, row: usize, sprite: Sprite) -> bool {
-        let mut collision = false;
-        for pixel in sprite.into_iter() {
-            let (pixel_x, pixel_y) = pixel;
-            let (pos_x, pos_y) = (
-                (pixel_x + column) % self.columns,
-                ((pixel_y + row) % self.rows),
-            );
-            self.pixels[pos_y][pos_x] ^= true;
-            collision |= !self.pixels[pos_y][pos_x];
-        }
-        println!("add_sprite");
-        println!("{:?}", &self);
-        println!("add_sprite end");
-        collision
+        osd.draw(&mut self.canvas, &self.palette);
+        self.canvas.present();
+        Ok(())
     }
 
-    fn draw_on_canvas(&mut self, canvas: &mut WindowCanvas) {
-        //println!("{:?}", &self);
-        let mut rects: Vec<Rect> = Vec::new();
-        for (j, row) in self.pixels.iter().enumerate() {
-            for (i, column) in row.into_iter().enumerate() {
-                if *column == true {
-                    let rect = Rect::new(
-                        (i * PIXEL_SIZE as usize) as i32,
-                        (j * PIXEL_SIZE as usize) as i32,
-                        PIXEL_SIZE,
-                        PIXEL_SIZE,
-                    );
-                    rects.push(rect);
-                }
+    //The texture, made again only when the pixel buffer changes size.
+    fn texture(&mut self, columns: u32, rows: u32) -> Result<&mut Texture, String> {
+        if let Some(texture) = self.texture.take() {
+            let query = texture.query();
+            if (query.width, query.height) == (columns, rows) {
+                return Ok(self.texture.get_or_insert(texture));
             }
+            //The canvas that made it is still alive.
+            unsafe { texture.destroy() };
         }
-        canvas.fill_rects(&rects);
+        let texture = self
+            .texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, columns, rows)
+            .map_err(|e| e.to_string())?;
+        Ok(self.texture.get_or_insert(texture))
     }
 
-    fn clear(&mut self) {
-        self.pixels = vec![vec![false; self.columns]; self.rows];
+    pub fn palette(&self) -> &Palette {
+        &self.palette
     }
-}
 
-impl Debug for PixelBuffer {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        println!("----PIXELBUFFER DUMP START----");
-        for (j, row) in self.pixels.iter().enumerate() {
-            for (i, column) in row.into_iter().enumerate() {
-                match *column {
-                    true => print!("*"),
-                    false => print!("-"),
-                }
-            }
-            println!();
-        }
-        println!("----PIXELBUFFER DUMP END----");
-        Ok(())
+    pub fn set_persistence(&mut self, decay: f32) {
+        self.persistence = Persistence::new(decay);
     }
 }
 
-#[derive(Debug)]
-pub struct Sprite {
-    pixels_on: Vec<(usize, usize)>,
-}
-
-impl Sprite {
-    pub fn new(bytes: &[u8]) -> Sprite {
-        let mut pixels_on: Vec<(usize, usize)> = Vec::new();
-        let bits = BitVec::from_bytes(&bytes);
-        let mut row = 0;
-        for line in bits.as_slice().chunks(8) {
-            for (col, bit) in line.into_iter().enumerate() {
-                if *bit == true {
-                    pixels_on.push((col, row));
-                }
-            }
-            row += 1;
+//Largest rectangle with the content's aspect ratio that fits the output, centered.
+fn letterbox(output: (u32, u32), content: (u32, u32), scaling: Scaling) -> Rect {
+    let (out_w, out_h) = output;
+    let (content_w, content_h) = content;
+    let (width, height) = match scaling {
+        Scaling::Integer => {
+            let factor = std::cmp::max(1, std::cmp::min(out_w / content_w, out_h / content_h));
+            (content_w * factor, content_h * factor)
         }
-        Sprite { pixels_on }
-    }
-    pub fn pixels(&self) -> Vec<(usize, usize)> {
-        self.pixels_on.clone()
-    }
+        Scaling::Smooth => {
+            let factor = f64::min(
+                out_w as f64 / content_w as f64,
+                out_h as f64 / content_h as f64,
+            );
+            (
+                (content_w as f64 * factor) as u32,
+                (content_h as f64 * factor) as u32,
+            )
+        }
+    };
+    let x = (out_w as i32 - width as i32) / 2;
+    let y = (out_h as i32 - height as i32) / 2;
+    Rect::new(x, y, width, height)
 }
 
-//Sprite is a collection of visible pixels positions relative to the beginning of the sprite (0, 0)
-impl IntoIterator for Sprite {
-    type Item = (usize, usize);
-    type IntoIter = std::vec::IntoIter<(usize, usize)>;
+#[cfg(test)]
+mod test {
+    use crate::display::{letterbox, Scaling};
+    use sdl2::rect::Rect;
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.pixels_on.into_iter()
+    #[test]
+    fn letterbox_integer_test() {
+        let rect = letterbox((700, 400), (64, 32), Scaling::Integer);
+        assert_eq!(rect, Rect::new(30, 40, 640, 320));
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::display::Sprite;
+    #[test]
+    fn letterbox_smooth_test() {
+        let rect = letterbox((700, 400), (64, 32), Scaling::Smooth);
+        assert_eq!(rect, Rect::new(0, 25, 700, 350));
+    }
 
     #[test]
-    fn test_sprite() {
-        let bytes = &[0xF0, 0x90, 0x90, 0x90, 0xF0];
-        let sprite = Sprite::new(bytes);
+    fn letterbox_too_small_window_test() {
+        let rect = letterbox((32, 16), (64, 32), Scaling::Integer);
+        assert_eq!(rect, Rect::new(-16, -8, 64, 32));
     }
 }
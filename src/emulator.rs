@@ -0,0 +1,245 @@
+use crate::config::Config;
+use crate::cpu::{Cpu, CpuFault};
+use crate::disasm::Symbols;
+use crate::font::{Font, FontError};
+use crate::framebuffer::PixelBuffer;
+use crate::keypad::Keypad;
+use crate::mem::{Memory, OutOfRange};
+use crate::memmap::MemoryMap;
+use crate::quirks::Quirks;
+use crate::rom::{Rom, RomError};
+use crate::sound::{SoundEvent, SoundState};
+use crate::state::SaveState;
+use crate::trace::TraceWriter;
+use log::{debug, error};
+use std::io::Write;
+
+//Instructions executed between two 60Hz timer ticks.
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+pub const MAX_INSTRUCTIONS_PER_FRAME: u32 = 1000;
+
+//Headless CHIP-8: memory, cpu, screen and keypad without any frontend attached.
+pub struct Emulator {
+    memory: Memory,
+    cpu: Cpu,
+    pixels: PixelBuffer,
+    keypad: Keypad,
+    instructions_per_frame: u32,
+    //Timer ticks since the emulator was created, never reset.
+    ticks: u64,
+    steps_this_tick: u32,
+    sound: SoundState,
+    sound_events: Vec<SoundEvent>,
+    memory_map: MemoryMap,
+    font: Font,
+    trace: Option<TraceWriter>,
+    //Labels of the loaded program, for traces.
+    symbols: Symbols,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Emulator::new()
+    }
+}
+
+impl Emulator {
+    pub fn new() -> Emulator {
+        Emulator {
+            memory: Memory::new(),
+            cpu: Cpu::new(),
+            pixels: PixelBuffer::default(),
+            keypad: Keypad::new(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            ticks: 0,
+            steps_this_tick: 0,
+            sound: SoundState::default(),
+            sound_events: Vec::new(),
+            memory_map: MemoryMap::default(),
+            font: Font::default(),
+            trace: None,
+            symbols: Symbols::default(),
+        }
+    }
+
+    fn load_rom(&mut self, rom: &Rom, offset: u16) {
+        for (i, byte) in rom.as_bytes().iter().enumerate() {
+            self.memory.write_8(*byte, offset + i as u16);
+        }
+    }
+    //A ROM too large for the memory map is refused and the emulator left as it was.
+    pub fn load(&mut self, rom: &Rom) -> Result<(), RomError> {
+        let map = self.memory_map;
+        rom.fits(&map)?;
+        self.memory = Memory::with_size(map.size);
+        self.pixels.clear();
+        self.keypad.release_all();
+        self.load_rom(rom, map.load_address);
+        self.font.write_to(&mut self.memory, &map);
+        self.symbols = rom.symbols().clone();
+        self.cpu.reset();
+        self.update_sound();
+        debug!("Loaded {} byte ROM", rom.as_bytes().len());
+        Ok(())
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            cpu: self.cpu.state(),
+            ticks: self.ticks,
+            memory: self.memory.bytes().to_vec(),
+            pixels: self.pixels.clone(),
+        }
+    }
+
+    //Resumes from a snapshot, keeping the current quirks and speed.
+    pub fn load_state(&mut self, state: &SaveState) {
+        self.memory = Memory::with_size(state.memory.len());
+        for (address, byte) in state.memory.iter().enumerate() {
+            self.memory.write_8(*byte, address as u16);
+        }
+        self.pixels = state.pixels.clone();
+        self.keypad.release_all();
+        self.cpu.restore(&state.cpu);
+        self.ticks = state.ticks;
+        self.steps_this_tick = 0;
+        self.update_sound();
+    }
+
+    //Emulation settings from the config, applied before the ROM is loaded.
+    pub fn configure(&mut self, config: &Config) -> Result<(), FontError> {
+        let font = match &config.font_file {
+            Some(path) => Font::load(path)?,
+            None => config.font.font(),
+        };
+        self.set_quirks(config.quirks);
+        self.set_memory_map(config.memory_map);
+        self.set_out_of_range(config.out_of_range);
+        self.set_font(font);
+        self.set_instructions_per_frame(config.instructions_per_frame);
+        Ok(())
+    }
+
+    //Takes effect on the next load.
+    pub fn set_memory_map(&mut self, memory_map: MemoryMap) {
+        self.memory_map = memory_map;
+        self.cpu.set_memory_map(memory_map);
+    }
+
+    //Takes effect on the next load.
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+    }
+
+    pub fn memory_map(&self) -> MemoryMap {
+        self.memory_map
+    }
+
+    //Every executed instruction gets written here, None turns tracing off.
+    pub fn set_trace(&mut self, writer: Option<Box<dyn Write>>) {
+        self.trace = writer.map(TraceWriter::new);
+    }
+
+    //Execute a single instruction.
+    pub fn step(&mut self) -> Result<(), CpuFault> {
+        if let Some(trace) = self.trace.as_mut() {
+            if let Err(e) = trace.record(&self.cpu, &self.memory, &self.symbols) {
+                error!("Could not write trace, tracing stopped: {}", e);
+                self.trace = None;
+            }
+        }
+        self.cpu
+            .step(&mut self.memory, &mut self.pixels, &self.keypad)?;
+        self.update_sound();
+        self.steps_this_tick += 1;
+        Ok(())
+    }
+
+    //Execute one 60Hz frame worth of instructions, then tick the timers.
+    //A fault stops the frame early and leaves the timers alone.
+    pub fn run_frame(&mut self) -> Result<(), CpuFault> {
+        while self.steps_this_tick < self.instructions_per_frame {
+            self.step()?;
+        }
+        self.cpu.tick_timers();
+        self.ticks += 1;
+        self.steps_this_tick = 0;
+        self.update_sound();
+        Ok(())
+    }
+
+    //Emulated time in timer ticks. Instructions are spread evenly over a tick.
+    pub fn time(&self) -> f64 {
+        let ipf = self.instructions_per_frame.max(1);
+        self.ticks as f64 + self.steps_this_tick.min(ipf) as f64 / ipf as f64
+    }
+
+    //Records sound changes at the current time.
+    fn update_sound(&mut self) {
+        let sound = self.cpu.sound();
+        if sound != self.sound {
+            let time = self.time();
+            for change in self.sound.changes_to(&sound) {
+                self.sound_events.push(SoundEvent { time, change });
+            }
+            self.sound = sound;
+        }
+    }
+
+    //Sound changes since the last call, oldest first.
+    pub fn take_sound_events(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sound_events)
+    }
+
+    pub fn run_frames(&mut self, frames: u32) -> Result<(), CpuFault> {
+        for _ in 0..frames {
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+
+    pub fn set_out_of_range(&mut self, out_of_range: OutOfRange) {
+        self.cpu.set_out_of_range(out_of_range);
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+    }
+
+    //Seed for CXNN, kept across ROM loads.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.cpu.seed(seed);
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions: u32) {
+        self.instructions_per_frame = instructions;
+    }
+
+    pub fn pixels(&self) -> &PixelBuffer {
+        &self.pixels
+    }
+
+    pub fn keypad(&self) -> &Keypad {
+        &self.keypad
+    }
+
+    pub fn keypad_mut(&mut self) -> &mut Keypad {
+        &mut self.keypad
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.cpu.sound_active()
+    }
+}
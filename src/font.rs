@@ -0,0 +1,316 @@
+use crate::mem::Memory;
+use crate::memmap::MemoryMap;
+use snafu::{ensure, ResultExt, Snafu};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const SMALL_GLYPH_BYTES: u16 = 5;
+pub const BIG_GLYPH_BYTES: u16 = 10;
+const SMALL_FONT_BYTES: usize = 16 * SMALL_GLYPH_BYTES as usize;
+const BIG_FONT_BYTES: usize = 16 * BIG_GLYPH_BYTES as usize;
+//SUPER-CHIP's big font only has the digits 0-9.
+const BIG_DIGITS_BYTES: usize = 10 * BIG_GLYPH_BYTES as usize;
+
+#[derive(Debug, Snafu)]
+pub enum FontError {
+    #[snafu(display("Could not read font {}: {}", filename.display(), source))]
+    ReadError {
+        filename: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display(
+        "Font is {} bytes, expected 80 for a small font, 180 or 240 with a big font",
+        size
+    ))]
+    BadSize { size: usize },
+}
+
+//Hex digits 0-F, 4x5 pixels each, stored in the high nibble.
+//Shapes from the interpreters, as collected by Octo.
+pub const VIP_FONT: [u8; SMALL_FONT_BYTES] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0x70, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+pub const DREAM6800_FONT: [u8; SMALL_FONT_BYTES] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+pub const ETI660_FONT: [u8; SMALL_FONT_BYTES] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+//Also what most emulators use, and this one did before fonts could be picked.
+pub const SCHIP_FONT: [u8; SMALL_FONT_BYTES] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+//8x10 digits for FX30: SUPER-CHIP 1.1's 0-9, then Octo's A-F for XO-CHIP.
+pub const BIG_FONT: [u8; BIG_FONT_BYTES] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum BuiltinFont {
+    Vip,
+    Dream6800,
+    Eti660,
+    #[default]
+    Schip,
+}
+
+impl BuiltinFont {
+    pub fn font(self) -> Font {
+        let small = match self {
+            BuiltinFont::Vip => VIP_FONT,
+            BuiltinFont::Dream6800 => DREAM6800_FONT,
+            BuiltinFont::Eti660 => ETI660_FONT,
+            BuiltinFont::Schip => SCHIP_FONT,
+        };
+        Font {
+            small,
+            big: BIG_FONT,
+        }
+    }
+}
+
+impl FromStr for BuiltinFont {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vip" => Ok(BuiltinFont::Vip),
+            "dream6800" => Ok(BuiltinFont::Dream6800),
+            "eti660" => Ok(BuiltinFont::Eti660),
+            "schip" => Ok(BuiltinFont::Schip),
+            _ => Err(format!(
+                "Unknown font {}, use vip, dream6800, eti660 or schip",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for BuiltinFont {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            BuiltinFont::Vip => "vip",
+            BuiltinFont::Dream6800 => "dream6800",
+            BuiltinFont::Eti660 => "eti660",
+            BuiltinFont::Schip => "schip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//Glyphs for FX29 and FX30.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub small: [u8; SMALL_FONT_BYTES],
+    pub big: [u8; BIG_FONT_BYTES],
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        BuiltinFont::default().font()
+    }
+}
+
+impl Font {
+    //80 bytes of small font, optionally followed by big digits 0-9 or 0-F.
+    //Big glyphs the file leaves out come from the built-in big font.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Font, FontError> {
+        let size = bytes.len();
+        let big_size = size.saturating_sub(SMALL_FONT_BYTES);
+        ensure!(
+            size >= SMALL_FONT_BYTES && [0, BIG_DIGITS_BYTES, BIG_FONT_BYTES].contains(&big_size),
+            BadSize { size }
+        );
+        let mut font = Font::default();
+        font.small.copy_from_slice(&bytes[..SMALL_FONT_BYTES]);
+        font.big[..big_size].copy_from_slice(&bytes[SMALL_FONT_BYTES..]);
+        Ok(font)
+    }
+
+    pub fn load<T: AsRef<Path>>(path: T) -> Result<Font, FontError> {
+        let filename = path.as_ref();
+        let bytes = fs::read(filename).context(ReadError { filename })?;
+        Font::from_bytes(&bytes)
+    }
+
+    //Copies the glyphs to where the memory map puts them.
+    pub fn write_to(&self, memory: &mut Memory, map: &MemoryMap) {
+        let mut write = |glyphs: &[u8], start: u16| {
+            for (offset, &byte) in glyphs.iter().enumerate() {
+                memory.write_8(byte, start + offset as u16);
+            }
+        };
+        write(&self.small, map.small_font);
+        if let Some(big_font) = map.big_font {
+            write(&self.big, big_font);
+        }
+    }
+}
+
+//Where the glyphs are in memory, the CPU asks here instead of knowing the layout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontAddresses {
+    small: u16,
+    big: Option<u16>,
+}
+
+impl Default for FontAddresses {
+    fn default() -> Self {
+        FontAddresses::new(&MemoryMap::default())
+    }
+}
+
+impl FontAddresses {
+    pub fn new(map: &MemoryMap) -> FontAddresses {
+        FontAddresses {
+            small: map.small_font,
+            big: map.big_font,
+        }
+    }
+
+    //Only the low nibble picks the digit, like the VIP.
+    pub fn small_glyph(&self, digit: u8) -> u16 {
+        self.small + (digit & 0xF) as u16 * SMALL_GLYPH_BYTES
+    }
+
+    //None when the platform has no big font.
+    pub fn big_glyph(&self, digit: u8) -> Option<u16> {
+        self.big
+            .map(|big| big + (digit & 0xF) as u16 * BIG_GLYPH_BYTES)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::font::{BuiltinFont, Font, FontAddresses, FontError, BIG_FONT, SCHIP_FONT};
+    use crate::mem::Memory;
+    use crate::memmap::MemoryMap;
+
+    #[test]
+    fn addresses_test() {
+        let vip = FontAddresses::new(&MemoryMap::vip());
+        assert_eq!(vip.small_glyph(0xA), 50);
+        assert_eq!(vip.small_glyph(0x1A), 50);
+        assert_eq!(vip.big_glyph(1), None);
+        let schip = FontAddresses::new(&MemoryMap::schip());
+        assert_eq!(schip.big_glyph(2), Some(0x50 + 20));
+    }
+
+    #[test]
+    fn write_test() {
+        let mut memory = Memory::new();
+        BuiltinFont::Dream6800
+            .font()
+            .write_to(&mut memory, &MemoryMap::schip());
+        assert_eq!(memory.read_range(5, 5), &[0x40; 5]);
+        assert_eq!(memory.read_range(0x50, 10), &BIG_FONT[..10]);
+    }
+
+    #[test]
+    fn from_bytes_test() {
+        let mut bytes = vec![0x11; 80];
+        let font = Font::from_bytes(&bytes).unwrap();
+        assert_eq!(&font.small[..], &bytes[..]);
+        assert_eq!(&font.big[..], &BIG_FONT[..]);
+
+        bytes.extend(vec![0x22; 100]);
+        let font = Font::from_bytes(&bytes).unwrap();
+        assert_eq!(&font.big[..100], &[0x22; 100][..]);
+        assert_eq!(&font.big[100..], &BIG_FONT[100..]);
+
+        assert!(matches!(
+            Font::from_bytes(&SCHIP_FONT[..79]),
+            Err(FontError::BadSize { size: 79 })
+        ));
+    }
+
+    #[test]
+    fn parse_test() {
+        for name in &["vip", "dream6800", "eti660", "schip"] {
+            let font: BuiltinFont = name.parse().unwrap();
+            assert_eq!(font.to_string(), *name);
+        }
+        assert!("comic".parse::<BuiltinFont>().is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::utils::BitVec;
+use std::fmt::{Debug, Error, Formatter};
+
+//Low resolution CHIP-8 screen size.
+pub const COLUMNS: usize = 64;
+pub const ROWS: usize = 32;
+
+//pixelbuffer is arrray representing what is shown on the screen.
+#[derive(Clone)]
+pub struct PixelBuffer {
+    pixels: Vec<Vec<bool>>,
+    columns: usize,
+    rows: usize,
+}
+
+impl Default for PixelBuffer {
+    fn default() -> Self {
+        PixelBuffer::new(COLUMNS, ROWS)
+    }
+}
+
+impl PixelBuffer {
+    pub fn new(columns: usize, rows: usize) -> PixelBuffer {
+        PixelBuffer {
+            pixels: vec![vec![false; columns]; rows],
+            columns,
+            rows,
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    //Pixel at (column, row); anything outside the screen is off.
+    pub fn get(&self, column: usize, row: usize) -> bool {
+        self.pixels
+            .get(row)
+            .and_then(|r| r.get(column))
+            .cloned()
+            .unwrap_or(false)
+    }
+
+    //Anything outside the screen is ignored.
+    pub fn set(&mut self, column: usize, row: usize, on: bool) {
+        if let Some(pixel) = self.pixels.get_mut(row).and_then(|r| r.get_mut(column)) {
+            *pixel = on;
+        }
+    }
+
+    pub fn add_sprite(&mut self, column: usize, row: usize, sprite: Sprite) -> bool {
+        let mut collision = false;
+        for pixel in sprite.into_iter() {
+            let (pixel_x, pixel_y) = pixel;
+            let (pos_x, pos_y) = (
+                (pixel_x + column) % self.columns,
+                ((pixel_y + row) % self.rows),
+            );
+            self.pixels[pos_y][pos_x] ^= true;
+            collision |= !self.pixels[pos_y][pos_x];
+        }
+        collision
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels = vec![vec![false; self.columns]; self.rows];
+    }
+}
+
+impl Debug for PixelBuffer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        writeln!(f, "----PIXELBUFFER DUMP START----")?;
+        for row in self.pixels.iter() {
+            for column in row.iter() {
+                match *column {
+                    true => write!(f, "*")?,
+                    false => write!(f, "-")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "----PIXELBUFFER DUMP END----")
+    }
+}
+
+#[derive(Debug)]
+pub struct Sprite {
+    pixels_on: Vec<(usize, usize)>,
+}
+
+impl Sprite {
+    pub fn new(bytes: &[u8]) -> Sprite {
+        let mut pixels_on: Vec<(usize, usize)> = Vec::new();
+        let bits = BitVec::from_bytes(bytes);
+        for (row, line) in bits.as_slice().chunks(8).enumerate() {
+            for (col, bit) in line.iter().enumerate() {
+                if *bit {
+                    pixels_on.push((col, row));
+                }
+            }
+        }
+        Sprite { pixels_on }
+    }
+    pub fn pixels(&self) -> Vec<(usize, usize)> {
+        self.pixels_on.clone()
+    }
+}
+
+//Sprite is a collection of visible pixels positions relative to the beginning of the sprite (0, 0)
+impl IntoIterator for Sprite {
+    type Item = (usize, usize);
+    type IntoIter = std::vec::IntoIter<(usize, usize)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pixels_on.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::framebuffer::Sprite;
+
+    #[test]
+    fn test_sprite() {
+        let bytes = &[0xF0, 0x90, 0x90, 0x90, 0xF0];
+        let pixels = Sprite::new(bytes).pixels();
+        //The font's 0: a box four pixels wide and five high.
+        assert_eq!(pixels.len(), 14);
+        assert_eq!(pixels[..4], [(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(pixels[4..6], [(0, 1), (3, 1)]);
+        assert!(!pixels.contains(&(1, 1)));
+    }
+}
@@ -0,0 +1,45 @@
+//Number of keys on the hex keypad, 0x0 to 0xF.
+pub const KEYS: usize = 16;
+
+//State of the CHIP-8 hex keypad, filled in by whatever frontend is running.
+#[derive(Debug, Default, Clone)]
+pub struct Keypad {
+    keys: [bool; KEYS],
+}
+
+impl Keypad {
+    pub fn new() -> Keypad {
+        Keypad::default()
+    }
+
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        if let Some(state) = self.keys.get_mut(key as usize) {
+            *state = pressed;
+        }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.set(key, true);
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.set(key, false);
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys = [false; KEYS];
+    }
+
+    //Keys outside of 0x0-0xF are never pressed.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys.get(key as usize).cloned().unwrap_or(false)
+    }
+
+    //Lowest key that is currently held down.
+    pub fn first_pressed(&self) -> Option<u8> {
+        self.keys
+            .iter()
+            .position(|pressed| *pressed)
+            .map(|key| key as u8)
+    }
+}
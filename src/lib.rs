@@ -0,0 +1,26 @@
+pub mod cartridge;
+pub mod command;
+pub mod config;
+pub mod cpu;
+pub mod disasm;
+pub mod emulator;
+pub mod font;
+pub mod framebuffer;
+pub mod keypad;
+pub mod mem;
+pub mod memmap;
+pub mod octo;
+pub mod palette;
+pub mod persistence;
+pub mod quirks;
+pub mod record;
+pub mod regression;
+pub mod rom;
+pub mod romdb;
+pub mod screenshot;
+pub mod sound;
+pub mod state;
+pub mod term;
+pub mod trace;
+mod utils;
+pub mod wav;
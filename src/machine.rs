@@ -1,137 +1,364 @@
-use snafu::{ResultExt, Snafu};
-use std::fs::File;
-use std::io::Read;
-use std::path::{Path, PathBuf};
+use chip8forever::command::{timestamp_millis, Command, Controller, Handled};
+use chip8forever::emulator::Emulator;
+use chip8forever::record::Recorder;
+use chip8forever::rom::{Rom, RomError};
+use chip8forever::screenshot;
 
 use crate::audio::AudioSubsystem;
-use crate::cpu::Cpu;
-use crate::display::{DisplaySubsystem, Sprite};
+use crate::display::DisplaySubsystem;
 use crate::input::InputSubsystem;
-use crate::mem::Memory;
+use crate::osd::{Osd, RateCounter};
+use crate::watch::{changed_bytes, RomWatcher};
+use log::{error, info};
+use sdl2::event::Event;
 use sdl2::keyboard::Scancode;
-use sdl2::pixels::Color;
-
-#[derive(Debug, Snafu)]
-pub enum RomError {
-    #[snafu(display("Could not load ROM from file {}: {}", filename.display(), source))]
-    FileError {
-        filename: PathBuf,
-        source: std::io::Error,
-    },
-}
-#[derive(Debug)]
-pub struct Rom {
-    content: Vec<u8>,
-}
-
-impl Rom {
-    pub fn from_file<T: AsRef<Path>>(path: T) -> Result<Self, RomError> {
-        let filename = path.as_ref();
-        let mut file = File::open(filename).context(FileError {
-            filename: filename.to_path_buf(),
-        })?;
-
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).context(FileError {
-            filename: filename.to_path_buf(),
-        })?;
-        println!("ROM size: {}", buffer.len());
-        Ok(Rom { content: buffer })
-    }
-
-    //I don't know how to return iter, so I will just return a whole vec...
-    //I know, weak.
-    pub fn get_bytes(&self) -> Vec<u8> {
-        self.content.clone()
-    }
-}
+use std::path::Path;
 
+//SDL frontend around the headless emulator.
 pub struct Machine {
-    memory: Memory,
-    cpu: Cpu,
+    controller: Controller,
     input: InputSubsystem,
     display: DisplaySubsystem,
     audio: AudioSubsystem,
+    screenshot_scale: u32,
+    record_scale: u32,
+    persistence: f32,
+    recorder: Option<Recorder>,
+    osd: Osd,
+    rates: RateCounter,
+    watcher: Option<RomWatcher>,
 }
 
 impl Machine {
     pub fn new(input: InputSubsystem, display: DisplaySubsystem, audio: AudioSubsystem) -> Machine {
-        let memory = Memory::new();
-        let cpu = Cpu::new();
         Machine {
-            memory,
-            cpu,
+            controller: Controller::new(Emulator::new()),
             input,
             display,
             audio,
+            screenshot_scale: 10,
+            record_scale: 4,
+            persistence: 0.0,
+            recorder: None,
+            osd: Osd::new(),
+            rates: RateCounter::new(),
+            watcher: None,
+        }
+    }
+
+    pub fn set_screenshot_scale(&mut self, scale: u32) {
+        self.screenshot_scale = scale;
+    }
+
+    pub fn set_record_scale(&mut self, scale: u32) {
+        self.record_scale = scale;
+    }
+
+    //Afterglow used by both the window and recordings.
+    pub fn set_persistence(&mut self, decay: f32) {
+        self.persistence = decay;
+        self.display.set_persistence(decay);
+    }
+
+    //Where F5 save states go.
+    pub fn set_state_dir(&mut self, dir: &Path) {
+        self.controller.set_state_dir(dir);
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        self.controller.emulator()
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Emulator {
+        self.controller.emulator_mut()
+    }
+
+    pub fn init(&mut self, rom: Rom) -> Result<(), RomError> {
+        if let Some(profile) = rom.profile() {
+            self.osd.message(&profile.to_string());
+        }
+        self.controller.load(rom)
+    }
+
+    //Reloads the ROM whenever the file changes on disk.
+    pub fn watch(&mut self, path: &Path, entry: Option<&str>) {
+        self.watcher = Some(RomWatcher::new(path, entry));
+    }
+
+    //Restarts with the new ROM. Settings, the window and pause stay as they are,
+    //a ROM that does not load leaves the old one running.
+    fn check_rom(&mut self) {
+        let (result, name) = match self.watcher.as_mut() {
+            Some(watcher) => match watcher.poll() {
+                Some(result) => (result, watcher.path().display().to_string()),
+                None => return,
+            },
+            None => return,
+        };
+        let loaded = result.and_then(|rom| {
+            let old = self.controller.rom().map_or(&[][..], |old| old.as_bytes());
+            let changed = changed_bytes(old, rom.as_bytes());
+            self.controller.load(rom).map(|_| changed)
+        });
+        match loaded {
+            Ok(changed) => {
+                self.osd.set_fault(None);
+                self.notify(&format!("Reloaded {}, {} bytes changed", name, changed));
+            }
+            Err(e) => self.notify(&format!("Could not reload {}: {}", name, e)),
+        }
+    }
+
+    pub fn execute(&mut self, command: Command) {
+        match self.controller.execute(command) {
+            Handled::Done(message) => {
+                if command == Command::Reset {
+                    self.osd.set_fault(None);
+                }
+                if let Some(message) = message {
+                    self.notify(&message);
+                }
+            }
+            Handled::Frontend(command) => match command {
+                Command::ToggleFullscreen => self.display.toggle_fullscreen(),
+                Command::ToggleRecording => self.toggle_recording(),
+                Command::Screenshot => self.take_screenshot(),
+                Command::ToggleStats => self.osd.toggle_stats(),
+                Command::ToggleMute => match self.audio.toggle_mute() {
+                    true => self.notify("Sound off"),
+                    false => self.notify("Sound on"),
+                },
+                _ => {}
+            },
+        }
+        self.osd.set_state(self.state_label());
+    }
+
+    fn state_label(&self) -> Option<&'static str> {
+        if self.controller.paused() {
+            Some("Paused")
+        } else if self.controller.fast_forward() {
+            Some("Fast forward >>")
+        } else {
+            None
         }
     }
-    fn load_rom(&mut self, rom: Rom, offset: u16) {
-        let rom = rom.get_bytes();
-        for (i, byte) in rom.iter().enumerate() {
-            self.memory.write_8(*byte, offset + i as u16);
+
+    //Goes to the log and the overlay.
+    fn notify(&mut self, text: &str) {
+        info!("{}", text);
+        self.osd.message(text);
+    }
+
+    //Saves screenshot-<millis>.png in the working directory.
+    fn take_screenshot(&mut self) {
+        let filename = format!("screenshot-{}.png", timestamp_millis());
+        match screenshot::save_png(
+            self.controller.emulator().pixels(),
+            self.display.palette(),
+            self.screenshot_scale,
+            &filename,
+        ) {
+            Ok(()) => self.notify(&format!("Saved {}", filename)),
+            Err(e) => self.notify(&e.to_string()),
         }
     }
-    fn load_fonts(&mut self, offset: u16) {
-        let font_set: [u8; 80] = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ];
-        for (i, byte) in font_set.iter().enumerate() {
-            self.memory.write_8(*byte, offset + i as u16); // TODO: pass this offset somewhere to the MEMORY?
+
+    //Records the window from now on in the format picked by the extension.
+    pub fn start_recording(&mut self, path: &Path) {
+        match Recorder::create(
+            path,
+            *self.display.palette(),
+            self.persistence,
+            self.record_scale,
+        ) {
+            Ok(recorder) => {
+                self.notify(&format!("Recording to {}", path.display()));
+                self.recorder = Some(recorder);
+            }
+            Err(e) => self.notify(&e.to_string()),
+        }
+    }
+
+    //Starts recording-<millis>.gif in the working directory, or stops the current one.
+    fn toggle_recording(&mut self) {
+        match self.recorder.take() {
+            Some(recorder) => {
+                let frames = recorder.frames();
+                match recorder.finish() {
+                    Ok(()) => self.notify(&format!("Recording stopped after {} frames", frames)),
+                    Err(e) => self.notify(&e.to_string()),
+                }
+            }
+            None => {
+                let filename = format!("recording-{}.gif", timestamp_millis());
+                self.start_recording(Path::new(&filename));
+            }
         }
     }
-    pub fn init(&mut self, rom: Rom) {
-        self.load_rom(rom, 0x200);
-        self.load_fonts(0x0);
-        self.cpu.reset();
+
+    fn record_frame(&mut self) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.push_frame(self.controller.emulator().pixels()) {
+                self.recorder = None;
+                self.notify(&e.to_string());
+            }
+        }
     }
 
-    fn should_quit(
-        &self,
-        event: &Option<sdl2::event::Event>,
-        pressed_keys: &Vec<sdl2::keyboard::Scancode>,
-    ) -> bool {
-        if let Some(sdl2::event::Event::Quit { .. }) = event {
-            return true;
+    //A fault halts the emulator, the window stays open so the screen can be inspected.
+    fn run_emulator(&mut self) {
+        match self.controller.run() {
+            Ok(instructions) => self.rates.frame(instructions),
+            Err(fault) => {
+                error!("CPU fault: {}", fault);
+                self.osd.set_fault(Some(format!("CPU fault: {}", fault)));
+                self.rates.frame(0);
+            }
         }
-        let esc_pressed = pressed_keys
-            .iter()
-            .find(|key| **key == sdl2::keyboard::Scancode::Escape)
-            .is_some();
-        return esc_pressed;
-    }
-    pub fn run(&mut self) {
-        self.display.set_color(Color::RGB(0, 0, 0));
-        'main: loop {
-            let event = self.input.poll();
-            let keys = self.input.keys_pressed();
-
-            self.cpu.step(
-                &mut self.memory,
-                &mut self.display,
-                &mut self.input,
-                &mut self.audio,
-            );
-            //self.input.wait_for_keypress(Scancode::Space);
-            println!("Step");
-            if self.should_quit(&event, &keys) {
-                break 'main;
+    }
+
+    fn handle_beeper(&mut self) {
+        let running = self.controller.running();
+        let emulator = self.controller.emulator_mut();
+        let events = emulator.take_sound_events();
+        self.audio.push_events(&events, emulator.time());
+        self.audio.set_paused(!running);
+    }
+
+    //One iteration per 60Hz frame, paced by the display vsync. Stops when the window
+    //can no longer be drawn, finishing any recording first.
+    pub fn run(&mut self) -> Result<(), String> {
+        let mut result = Ok(());
+        while !self.controller.quit() {
+            while let Some(event) = self.input.poll() {
+                if let Some(command) = hotkey(&event) {
+                    self.execute(command);
+                }
             }
+            if self.controller.quit() {
+                break;
+            }
+            self.input
+                .update_keypad(self.controller.emulator_mut().keypad_mut());
+
+            self.check_rom();
+            self.run_emulator();
+            self.handle_beeper();
+            self.osd.set_stats(&self.rates);
+            result = self
+                .display
+                .update(self.controller.emulator().pixels(), &mut self.osd);
+            if result.is_err() {
+                break;
+            }
+            self.record_frame();
+        }
+        if self.recorder.is_some() {
+            self.toggle_recording();
+        }
+        result
+    }
+}
+
+//Esc quit, P pause, N frame advance, hold Tab fast forward, +/- speed, M mute,
+//F1 stats, F2 reset, F5 save state, F9 record, F11 fullscreen, F12 screenshot.
+fn hotkey(event: &Event) -> Option<Command> {
+    match event {
+        Event::Quit { .. } => Some(Command::Quit),
+        Event::KeyDown {
+            scancode: Some(scancode),
+            repeat,
+            ..
+        } => match (scancode, repeat) {
+            (Scancode::Escape, _) => Some(Command::Quit),
+            (Scancode::Equals, _) | (Scancode::KpPlus, _) => Some(Command::SpeedUp),
+            (Scancode::Minus, _) | (Scancode::KpMinus, _) => Some(Command::SlowDown),
+            (_, true) => None,
+            (Scancode::P, _) => Some(Command::TogglePause),
+            (Scancode::N, _) => Some(Command::FrameAdvance),
+            (Scancode::M, _) => Some(Command::ToggleMute),
+            (Scancode::Tab, _) => Some(Command::FastForward(true)),
+            (Scancode::F1, _) => Some(Command::ToggleStats),
+            (Scancode::F2, _) => Some(Command::Reset),
+            (Scancode::F5, _) => Some(Command::SaveState),
+            (Scancode::F9, _) => Some(Command::ToggleRecording),
+            (Scancode::F11, _) => Some(Command::ToggleFullscreen),
+            (Scancode::F12, _) => Some(Command::Screenshot),
+            _ => None,
+        },
+        Event::KeyUp {
+            scancode: Some(Scancode::Tab),
+            ..
+        } => Some(Command::FastForward(false)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::machine::{hotkey, Command};
+    use sdl2::event::Event;
+    use sdl2::keyboard::{Mod, Scancode};
+
+    fn key_down(scancode: Scancode, repeat: bool) -> Event {
+        Event::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: None,
+            scancode: Some(scancode),
+            keymod: Mod::empty(),
+            repeat,
         }
     }
+
+    #[test]
+    fn hotkey_test() {
+        assert_eq!(
+            hotkey(&key_down(Scancode::P, false)),
+            Some(Command::TogglePause)
+        );
+        assert_eq!(hotkey(&key_down(Scancode::F2, false)), Some(Command::Reset));
+        assert_eq!(
+            hotkey(&key_down(Scancode::F5, false)),
+            Some(Command::SaveState)
+        );
+        assert_eq!(
+            hotkey(&key_down(Scancode::Escape, false)),
+            Some(Command::Quit)
+        );
+        assert_eq!(hotkey(&Event::Quit { timestamp: 0 }), Some(Command::Quit));
+        //Keypad keys are not hotkeys.
+        assert_eq!(hotkey(&key_down(Scancode::Q, false)), None);
+    }
+
+    #[test]
+    fn hotkey_repeat_test() {
+        assert_eq!(hotkey(&key_down(Scancode::N, true)), None);
+        assert_eq!(
+            hotkey(&key_down(Scancode::Equals, true)),
+            Some(Command::SpeedUp)
+        );
+        assert_eq!(
+            hotkey(&key_down(Scancode::KpMinus, true)),
+            Some(Command::SlowDown)
+        );
+    }
+
+    #[test]
+    fn fast_forward_hold_test() {
+        assert_eq!(
+            hotkey(&key_down(Scancode::Tab, false)),
+            Some(Command::FastForward(true))
+        );
+        let release = Event::KeyUp {
+            timestamp: 0,
+            window_id: 0,
+            keycode: None,
+            scancode: Some(Scancode::Tab),
+            keymod: Mod::empty(),
+            repeat: false,
+        };
+        assert_eq!(hotkey(&release), Some(Command::FastForward(false)));
+    }
 }
@@ -1,18 +1,38 @@
 mod audio;
-mod cpu;
 mod display;
 mod input;
 mod machine;
-mod mem;
-mod utils;
+mod osd;
+mod watch;
 
-use sdl2;
-use snafu::Snafu;
-use std::path::PathBuf;
+use chip8forever::config::{self, AudioLayer, Config, Layer};
+use chip8forever::cpu::CpuFault;
+use chip8forever::emulator::Emulator;
+use chip8forever::font::{self, BuiltinFont};
+use chip8forever::mem::OutOfRange;
+use chip8forever::memmap::MemoryMap;
+use chip8forever::palette::Palette;
+use chip8forever::quirks::{QuirkOverride, Quirks};
+use chip8forever::record::{self, Recorder};
+use chip8forever::rom::{self, Rom};
+use chip8forever::screenshot;
+use chip8forever::sound::Waveform;
+use chip8forever::state::{self, SaveState};
+use chip8forever::wav::{self, WavRecorder};
+use log::warn;
+use sdl2::keyboard::Scancode;
+use snafu::{ensure, ResultExt, Snafu};
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
 
 use machine::*;
 
+//Real time frame length for headless runs without a frame count.
+const FRAME: Duration = Duration::from_micros(16_667);
+
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "Chip8Forever",
@@ -22,28 +42,391 @@ struct Options {
     /// Input file
     #[structopt(name = "path-to-rom", short = "r", long = "rom", parse(from_os_str))]
     rom_path: PathBuf,
+
+    /// ROM to use from a zip archive that has several
+    #[structopt(long = "entry")]
+    entry: Option<String>,
+
+    /// Memory layout: vip, eti660 (programs at 0x600), schip or xochip (64K)
+    #[structopt(long = "memory-map")]
+    memory_map: Option<MemoryMap>,
+
+    /// Built-in font: vip, dream6800, eti660 or schip
+    #[structopt(long = "font")]
+    font: Option<BuiltinFont>,
+
+    /// Font file: 80 bytes of 4x5 digits, optionally followed by 8x10 digits 0-9 or 0-F
+    #[structopt(long = "font-file", parse(from_os_str))]
+    font_file: Option<PathBuf>,
+
+    /// Past the end of memory: wrap, fault or ignore
+    #[structopt(long = "out-of-range")]
+    out_of_range: Option<OutOfRange>,
+
+    /// Instructions executed per 60Hz frame, 1 to 1000
+    #[structopt(long = "ipf")]
+    instructions_per_frame: Option<u32>,
+
+    /// Quirks preset: vip, chip48, schip or xochip
+    #[structopt(long = "quirks")]
+    quirks: Option<Quirks>,
+
+    /// Change one quirk of the preset, repeatable: shift-vy, vf-reset, jump-vx=on|off, memory-increment=x+1|x|unchanged
+    #[structopt(long = "quirk", number_of_values = 1)]
+    quirk_overrides: Vec<QuirkOverride>,
+
+    /// Seed for the random number generator, for reproducible runs
+    #[structopt(long = "seed")]
+    seed: Option<u32>,
+
+    /// Initial window size as a multiple of 64x32
+    #[structopt(long = "scale", default_value = "10")]
+    scale: u32,
+
+    /// Start in fullscreen
+    #[structopt(long = "fullscreen")]
+    fullscreen: bool,
+
+    /// Start with the beeper muted
+    #[structopt(long = "mute")]
+    mute: bool,
+
+    /// Window scaling: integer or smooth
+    #[structopt(long = "scaling", default_value = "integer")]
+    scaling: display::Scaling,
+
+    /// Colours: classic, amber, green, lcd, octo or RRGGBB,RRGGBB (foreground,background)
+    #[structopt(long = "palette")]
+    palette: Option<Palette>,
+
+    /// Size of a CHIP-8 pixel in screenshots
+    #[structopt(long = "screenshot-scale", default_value = "10")]
+    screenshot_scale: u32,
+
+    /// Size of a CHIP-8 pixel in recordings
+    #[structopt(long = "record-scale", default_value = "4")]
+    record_scale: u32,
+
+    /// Afterglow of switched off pixels, 0 (off) to 0.99
+    #[structopt(long = "persistence", default_value = "0")]
+    persistence: f32,
+
+    /// Beeper waveform: square, sine, triangle or noise
+    #[structopt(long = "waveform")]
+    waveform: Option<Waveform>,
+
+    /// Beeper frequency in Hz
+    #[structopt(long = "frequency")]
+    frequency: Option<f32>,
+
+    /// Beeper volume, 0 to 1
+    #[structopt(long = "volume")]
+    volume: Option<f32>,
+
+    /// Run headless for this many frames instead of opening a window
+    #[structopt(long = "frames")]
+    frames: Option<u32>,
+
+    /// Run without a window in real time, or for --frames
+    #[structopt(long = "headless")]
+    headless: bool,
+
+    /// Do not open an audio device
+    #[structopt(long = "no-audio")]
+    no_audio: bool,
+
+    /// Do not reload the ROM when the file changes
+    #[structopt(long = "no-reload")]
+    no_reload: bool,
+
+    /// Save a PNG screenshot of the last frame on exit
+    #[structopt(long = "screenshot", parse(from_os_str))]
+    screenshot: Option<PathBuf>,
+
+    /// Record from the start: .gif, .y4m, raw RGB24 for anything else, - for Y4M on stdout
+    #[structopt(long = "record", parse(from_os_str))]
+    record: Option<PathBuf>,
+
+    /// Resume from a save state made with F5 or --save-state
+    #[structopt(long = "load-state", parse(from_os_str))]
+    load_state: Option<PathBuf>,
+
+    /// Save the state on exit
+    #[structopt(long = "save-state", parse(from_os_str))]
+    save_state: Option<PathBuf>,
+
+    /// Write every executed instruction to this file
+    #[structopt(long = "trace", parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// Write the audio of a headless run to a WAV file
+    #[structopt(long = "wav", parse(from_os_str), requires = "frames")]
+    wav: Option<PathBuf>,
+
+    /// Config file to use instead of the one in the user config directory
+    #[structopt(long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Print the effective settings for this ROM and exit
+    #[structopt(long = "print-config")]
+    print_config: bool,
+}
+
+impl Options {
+    //Settings given on the command line, applied over the config file.
+    fn layer(&self) -> Layer {
+        Layer {
+            palette: self.palette,
+            quirks: self.quirks,
+            quirk: self.quirk_overrides.clone(),
+            memory_map: self.memory_map,
+            font: self.font,
+            font_file: self.font_file.clone(),
+            out_of_range: self.out_of_range,
+            ipf: self.instructions_per_frame,
+            audio: AudioLayer {
+                waveform: self.waveform,
+                frequency: self.frequency,
+                volume: self.volume,
+                mute: if self.mute { Some(true) } else { None },
+            },
+            ..Layer::default()
+        }
+    }
 }
 
 #[derive(Debug, Snafu)]
 enum Error {
-    #[snafu(display("Error while attempting to load ROM"))]
-    RomError { source: machine::RomError },
+    #[snafu(display("Error while attempting to load ROM: {}", source))]
+    LoadRom { source: rom::RomError },
+    #[snafu(display("Error while saving screenshot: {}", source))]
+    Screenshot { source: screenshot::ScreenshotError },
+    #[snafu(display("Error while recording: {}", source))]
+    Record { source: record::RecordError },
+    #[snafu(display("CPU fault: {}", source))]
+    Fault { source: CpuFault },
+    #[snafu(display("Error while writing audio: {}", source))]
+    Wav { source: wav::WavError },
+    #[snafu(display("Could not create trace file {}: {}", filename.display(), source))]
+    Trace {
+        filename: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("{}", source))]
+    State { source: state::StateError },
+    #[snafu(display("Could not draw the window: {}", message))]
+    Draw { message: String },
+    #[snafu(display("Could not open a window: {}", message))]
+    Window { message: String },
+    #[snafu(display("{}", message))]
+    Keymap { message: String },
+    #[snafu(display("Window scale must be at least 1"))]
+    BadScale,
+    #[snafu(display("{}", source))]
+    LoadConfig { source: config::ConfigError },
+    #[snafu(display("{}", source))]
+    Font { source: font::FontError },
 }
 type Result<T, E = Error> = std::result::Result<T, E>;
 
-fn main() -> Result<(), Error> {
+fn main() {
+    //RUST_LOG=debug or RUST_LOG=chip8forever::cpu=trace for more.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     let opt = Options::from_args();
-    let rom = Rom::from_file(opt.rom_path);
-    let rom = rom.expect("Rom Error");
+    if let Err(e) = run(&opt) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(opt: &Options) -> Result<(), Error> {
+    ensure!(opt.scale >= 1, BadScale);
+    let rom = load_rom(opt)?;
+    let config = load_config(opt, &rom)?;
+    //Bad key names are config errors, even for runs that end up headless.
+    let keymap = input::keymap(&config.keys).map_err(|message| Error::Keymap { message })?;
+    if opt.print_config {
+        print!("{}", config);
+        return Ok(());
+    }
+
+    if opt.headless || opt.frames.is_some() {
+        return run_headless(opt, &config, &rom);
+    }
+    match open_window(opt, &config, keymap) {
+        Ok(mut machine) => {
+            configure(opt, &config, machine.emulator_mut())?;
+            machine.init(rom).context(LoadRom)?;
+            if !opt.no_reload && opt.rom_path != Path::new("-") {
+                machine.watch(&opt.rom_path, opt.entry.as_deref());
+            }
+            if let Some(path) = &opt.load_state {
+                let state = SaveState::load(path).context(State)?;
+                machine.emulator_mut().load_state(&state);
+            }
+            if let Some(path) = &opt.record {
+                machine.start_recording(path);
+            }
+            machine.run().map_err(|message| Error::Draw { message })?;
+            save_on_exit(opt, config.palette, machine.emulator())
+        }
+        Err(Error::Window { message }) => {
+            warn!("Could not open a window ({}), running headless", message);
+            run_headless(opt, &config, &rom)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+//Asks which ROM to use when an archive has several and nobody said with --entry.
+fn load_rom(opt: &Options) -> Result<Rom, Error> {
+    match Rom::from_file_entry(&opt.rom_path, opt.entry.as_deref()) {
+        Err(rom::RomError::SeveralRoms { names }) if io::stdin().is_terminal() => {
+            match choose_entry(&names) {
+                Some(name) => Rom::from_file_entry(&opt.rom_path, Some(name)),
+                None => Err(rom::RomError::SeveralRoms { names }),
+            }
+        }
+        result => result,
+    }
+    .context(LoadRom)
+}
+
+//None when standard input ends without a valid choice.
+fn choose_entry(names: &[String]) -> Option<&str> {
+    for (number, name) in names.iter().enumerate() {
+        eprintln!("{:>3}) {}", number + 1, name);
+    }
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        eprint!("Which ROM [1-{}]? ", names.len());
+        let line = lines.next()?.ok()?;
+        match line.trim().parse::<usize>() {
+            Ok(number) if (1..=names.len()).contains(&number) => return Some(&names[number - 1]),
+            _ => eprintln!("Enter a number between 1 and {}", names.len()),
+        }
+    }
+}
 
-    let context = sdl2::init().unwrap();
-    let input = input::InputSubsystem::new(&context);
-    let display = display::DisplaySubsystem::new(&context, "CHIPERERE", 640, 320);
-    let audio = audio::AudioSubsystem::new(&context);
+//Built-in defaults, the config file, its section for this ROM, then the command line.
+fn load_config(opt: &Options, rom: &Rom) -> Result<Config, Error> {
+    Config::load(opt.config.as_deref(), rom, &opt.layer()).context(LoadConfig)
+}
+
+//Emulation settings shared by the window and headless runs, applied before the ROM is loaded.
+fn configure(opt: &Options, config: &Config, emulator: &mut Emulator) -> Result<(), Error> {
+    emulator.configure(config).context(Font)?;
+    if let Some(seed) = opt.seed {
+        emulator.set_seed(seed);
+    }
+    emulator.set_trace(open_trace(opt)?);
+    Ok(())
+}
+
+//Only failures to bring up SDL video are Window errors, those fall back to headless.
+fn open_window(opt: &Options, config: &Config, keymap: Vec<Scancode>) -> Result<Machine, Error> {
+    let window = |message| Error::Window { message };
+    let context = sdl2::init().map_err(window)?;
+    let input = input::InputSubsystem::new(&context, keymap).map_err(window)?;
+    let mut display = display::DisplaySubsystem::new(
+        &context,
+        "CHIPERERE",
+        64 * opt.scale,
+        32 * opt.scale,
+        opt.scaling,
+        config.palette,
+    )
+    .map_err(window)?;
+    if opt.fullscreen {
+        display.toggle_fullscreen();
+    }
+    let mut audio = match opt.no_audio {
+        true => audio::AudioSubsystem::null(),
+        false => audio::AudioSubsystem::new(&context, config.tone),
+    };
+    audio.set_muted(config.mute);
 
     let mut machine = Machine::new(input, display, audio);
-    machine.init(rom);
-    machine.run();
+    machine.set_screenshot_scale(opt.screenshot_scale);
+    machine.set_record_scale(opt.record_scale);
+    machine.set_persistence(opt.persistence);
+    machine.set_state_dir(&config.state_dir);
+    Ok(machine)
+}
+
+fn open_trace(opt: &Options) -> Result<Option<Box<dyn Write>>, Error> {
+    match &opt.trace {
+        Some(filename) => {
+            let file = File::create(filename).context(Trace { filename })?;
+            Ok(Some(Box::new(BufWriter::new(file))))
+        }
+        None => Ok(None),
+    }
+}
+
+//Runs without window or sound: as fast as possible for --frames, otherwise in real time until killed.
+fn run_headless(opt: &Options, config: &Config, rom: &Rom) -> Result<(), Error> {
+    let mut emulator = Emulator::new();
+    configure(opt, config, &mut emulator)?;
+    emulator.load(rom).context(LoadRom)?;
+    if let Some(path) = &opt.load_state {
+        emulator.load_state(&SaveState::load(path).context(State)?);
+    }
+    let mut recorder = match &opt.record {
+        Some(path) => Some(
+            Recorder::create(path, config.palette, opt.persistence, opt.record_scale)
+                .context(Record)?,
+        ),
+        None => None,
+    };
+    let mut wav = match &opt.wav {
+        Some(path) => Some(WavRecorder::create(path, config.tone, emulator.time()).context(Wav)?),
+        None => None,
+    };
+    let mut frame = 0;
+    let mut fault = None;
+    while opt.frames.is_none_or(|frames| frame < frames) {
+        let frame_start = Instant::now();
+        //Stops the run, but the recordings up to here still get finished.
+        if let Err(source) = emulator.run_frame() {
+            fault = Some(source);
+            break;
+        }
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.push_frame(emulator.pixels()).context(Record)?;
+        }
+        if let Some(wav) = wav.as_mut() {
+            let events = emulator.take_sound_events();
+            wav.push(&events, emulator.time()).context(Wav)?;
+        }
+        if opt.frames.is_none() {
+            if let Some(left) = FRAME.checked_sub(frame_start.elapsed()) {
+                std::thread::sleep(left);
+            }
+        }
+        frame += 1;
+    }
+    if let Some(recorder) = recorder {
+        recorder.finish().context(Record)?;
+    }
+    if let Some(wav) = wav {
+        wav.finish().context(Wav)?;
+    }
+    if let Some(source) = fault {
+        return Err(Error::Fault { source });
+    }
+    save_on_exit(opt, config.palette, &emulator)
+}
 
+//--save-state and --screenshot, for both the window and headless runs.
+fn save_on_exit(opt: &Options, palette: Palette, emulator: &Emulator) -> Result<(), Error> {
+    if let Some(path) = &opt.save_state {
+        emulator.save_state().save(path).context(State)?;
+    }
+    if let Some(path) = &opt.screenshot {
+        screenshot::save_png(emulator.pixels(), &palette, opt.screenshot_scale, path)
+            .context(Screenshot)?;
+    }
     Ok(())
 }
@@ -1,25 +1,118 @@
-use std::ops::Range;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+pub const MEM_SIZE: usize = 4096;
+//XO-CHIP's 64K, the most a 16 bit address reaches.
+pub const MAX_MEM_SIZE: usize = 0x10000;
+
+//What the CPU does when a program reaches past the end of memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutOfRange {
+    //Addresses wrap at the memory size, like the unused address lines on real hardware.
+    #[default]
+    Wrap,
+    //Stop with a CpuFault.
+    Fault,
+    //Log it, reads give zero and writes are dropped.
+    Ignore,
+}
+
+impl FromStr for OutOfRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(OutOfRange::Wrap),
+            "fault" => Ok(OutOfRange::Fault),
+            "ignore" => Ok(OutOfRange::Ignore),
+            _ => Err(format!(
+                "Unknown out of range behaviour {}, use wrap, fault or ignore",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for OutOfRange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            OutOfRange::Wrap => "wrap",
+            OutOfRange::Fault => "fault",
+            OutOfRange::Ignore => "ignore",
+        };
+        write!(f, "{}", name)
+    }
+}
 
-const MEM_SIZE: usize = 4096;
 pub struct Memory {
-    data: [u8; MEM_SIZE],
+    data: Vec<u8>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
+    }
 }
 
 impl Memory {
     pub fn new() -> Memory {
+        Memory::with_size(MEM_SIZE)
+    }
+
+    //Anywhere from 4K to 64K.
+    pub fn with_size(size: usize) -> Memory {
         Memory {
-            data: [0; MEM_SIZE],
+            data: vec![0; size.clamp(MEM_SIZE, MAX_MEM_SIZE)],
         }
     }
+
+    //Addresses past the end wrap around.
     pub fn write_8(&mut self, b: u8, addr: u16) {
-        self.data[addr as usize] = b;
+        let addr = self.wrap(addr as usize);
+        self.data[addr] = b;
     }
 
     pub fn read_8(&self, addr: u16) -> u8 {
-        self.data[addr as usize]
+        self.data[self.wrap(addr as usize)]
     }
 
+    //Cut short at the end of memory.
     pub fn read_range(&self, addr: u16, num: u16) -> &[u8] {
-        &self.data[addr as usize..(addr + num) as usize]
+        let start = (addr as usize).min(self.data.len());
+        let end = (start + num as usize).min(self.data.len());
+        &self.data[start..end]
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        addr < self.data.len()
+    }
+
+    pub fn wrap(&self, addr: usize) -> usize {
+        addr % self.data.len()
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mem::{Memory, OutOfRange};
+
+    #[test]
+    fn bounds_test() {
+        let mut memory = Memory::new();
+        memory.write_8(0xAB, 0x1001);
+        assert_eq!(memory.read_8(0x0001), 0xAB);
+        assert_eq!(memory.read_8(0xF001), 0xAB);
+        assert_eq!(memory.read_range(0xFFE, 4).len(), 2);
+        assert!(memory.read_range(0xFFFF, 0xFFFF).is_empty());
+        assert_eq!("fault".parse(), Ok(OutOfRange::Fault));
+        assert!("clamp".parse::<OutOfRange>().is_err());
     }
 }
@@ -0,0 +1,176 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+//Addresses start..end used by the interpreter. ROMs that would reach one are not loaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+}
+
+//Where things live in memory on one platform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryMap {
+    pub name: &'static str,
+    pub size: usize,
+    //Programs are loaded here.
+    pub load_address: u16,
+    pub initial_pc: u16,
+    //5 byte hex digits for FX29.
+    pub small_font: u16,
+    //10 byte digits for FX30 on platforms that have them.
+    pub big_font: Option<u16>,
+    pub reserved: &'static [Region],
+}
+
+//The real interpreters keep their fonts in ROM, outside of the CHIP-8 address space.
+//Like most emulators the fonts go at the bottom of the interpreter area instead.
+const VIP: MemoryMap = MemoryMap {
+    name: "vip",
+    size: 0x1000,
+    load_address: 0x200,
+    initial_pc: 0x200,
+    small_font: 0x000,
+    big_font: None,
+    //Interpreter, then its stack and variables and the display buffer.
+    reserved: &[
+        Region {
+            start: 0x000,
+            end: 0x200,
+        },
+        Region {
+            start: 0xEA0,
+            end: 0x1000,
+        },
+    ],
+};
+
+const ETI660: MemoryMap = MemoryMap {
+    name: "eti660",
+    size: 0x1000,
+    load_address: 0x600,
+    initial_pc: 0x600,
+    small_font: 0x000,
+    big_font: None,
+    //Interpreter, then its stack and variables and the display buffer.
+    reserved: &[
+        Region {
+            start: 0x000,
+            end: 0x600,
+        },
+        Region {
+            start: 0xEA0,
+            end: 0x1000,
+        },
+    ],
+};
+
+const SCHIP: MemoryMap = MemoryMap {
+    name: "schip",
+    size: 0x1000,
+    load_address: 0x200,
+    initial_pc: 0x200,
+    small_font: 0x000,
+    big_font: Some(0x050),
+    reserved: &[Region {
+        start: 0x000,
+        end: 0x200,
+    }],
+};
+
+//There is no long I load (F000 NNNN) yet, so I only gets past 0xFFF by adding
+//to it with FX1E, and jumps and calls stay in the first 4K.
+const XOCHIP: MemoryMap = MemoryMap {
+    name: "xochip",
+    size: 0x10000,
+    load_address: 0x200,
+    initial_pc: 0x200,
+    small_font: 0x000,
+    big_font: Some(0x050),
+    reserved: &[Region {
+        start: 0x000,
+        end: 0x200,
+    }],
+};
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        MemoryMap::vip()
+    }
+}
+
+impl MemoryMap {
+    pub fn vip() -> MemoryMap {
+        VIP
+    }
+
+    pub fn eti660() -> MemoryMap {
+        ETI660
+    }
+
+    pub fn schip() -> MemoryMap {
+        SCHIP
+    }
+
+    pub fn xochip() -> MemoryMap {
+        XOCHIP
+    }
+
+    //Room from the load address to the next reserved region or the end of memory.
+    pub fn max_program_size(&self) -> usize {
+        let load = self.load_address as usize;
+        let end = self
+            .reserved
+            .iter()
+            .map(|region| region.start)
+            .filter(|&start| start >= load)
+            .min()
+            .unwrap_or(self.size);
+        end - load
+    }
+}
+
+impl FromStr for MemoryMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vip" => Ok(MemoryMap::vip()),
+            "eti660" => Ok(MemoryMap::eti660()),
+            "schip" => Ok(MemoryMap::schip()),
+            "xochip" => Ok(MemoryMap::xochip()),
+            _ => Err(format!(
+                "Unknown memory map {}, use vip, eti660, schip or xochip",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for MemoryMap {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memmap::MemoryMap;
+
+    #[test]
+    fn program_size_test() {
+        assert_eq!(MemoryMap::vip().max_program_size(), 0xCA0);
+        assert_eq!(MemoryMap::eti660().max_program_size(), 0x8A0);
+        assert_eq!(MemoryMap::schip().max_program_size(), 0xE00);
+        assert_eq!(MemoryMap::xochip().max_program_size(), 0xFE00);
+    }
+
+    #[test]
+    fn parse_test() {
+        for name in &["vip", "eti660", "schip", "xochip"] {
+            let map: MemoryMap = name.parse().unwrap();
+            assert_eq!(map.to_string(), *name);
+        }
+        assert!("c64".parse::<MemoryMap>().is_err());
+    }
+}
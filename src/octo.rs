@@ -0,0 +1,656 @@
+use crate::disasm::Symbols;
+use crate::rom::PROGRAM_START;
+use snafu::Snafu;
+use std::collections::HashMap;
+
+#[derive(Debug, Snafu)]
+pub enum AssemblyError {
+    #[snafu(display("Line {}: {}", line, message))]
+    SyntaxError { line: usize, message: String },
+    #[snafu(display("Line {}: {} is not defined", line, name))]
+    Undefined { line: usize, name: String },
+    #[snafu(display("Line {}: {} has no matching end", line, block))]
+    Unclosed { line: usize, block: String },
+    #[snafu(display("The program has no main label"))]
+    NoMain,
+}
+
+//An assembled program, to be loaded at PROGRAM_START.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    pub symbols: Symbols,
+}
+
+//Assembles the commonly used part of Octo: labels, :const, :alias, :org, :call,
+//the statements for every CHIP-8 and SUPER-CHIP instruction, if/then, if/begin/else/end
+//and loop/while/again. Macros, :calc, :next, :unpack and the <, >, <=, >= comparisons
+//are not supported. Like Octo, the program starts with a jump to main.
+pub fn assemble(source: &str) -> Result<Program, AssemblyError> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source),
+        position: 0,
+        bytes: Vec::new(),
+        labels: HashMap::new(),
+        symbols: Symbols::default(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+    assembler.emit(0x1000);
+    while assembler.position < assembler.tokens.len() {
+        assembler.statement()?;
+    }
+    assembler.finish()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+//Octo source is whitespace separated tokens, '#' starts a comment.
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(number, line)| {
+            let code = line.split('#').next().unwrap_or("");
+            code.split_whitespace().map(move |text| Token {
+                text,
+                line: number + 1,
+            })
+        })
+        .collect()
+}
+
+fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+    match digit.len() {
+        1 => u8::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+//A 12 bit address to fill in once every label is known.
+struct Fixup {
+    offset: usize,
+    name: String,
+    line: usize,
+}
+
+enum Block {
+    //Offset of the jump taken when the condition is false.
+    If {
+        jump: usize,
+        line: usize,
+    },
+    Else {
+        jump: usize,
+        line: usize,
+    },
+    //Offsets of the jumps out of the loop made by while.
+    Loop {
+        start: u16,
+        exits: Vec<usize>,
+        line: usize,
+    },
+}
+
+//Skip instructions for a condition.
+struct Condition {
+    skip_if_true: u16,
+    skip_if_false: u16,
+}
+
+struct Assembler<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    bytes: Vec<u8>,
+    labels: HashMap<String, u16>,
+    symbols: Symbols,
+    constants: HashMap<String, i32>,
+    aliases: HashMap<String, u8>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+}
+
+impl<'a> Assembler<'a> {
+    fn line(&self) -> usize {
+        let index = self.position.min(self.tokens.len()).saturating_sub(1);
+        self.tokens.get(index).map_or(0, |token| token.line)
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, AssemblyError> {
+        SyntaxError {
+            line: self.line(),
+            message,
+        }
+        .fail()
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, AssemblyError> {
+        match self.tokens.get(self.position) {
+            Some(&token) => {
+                self.position += 1;
+                Ok(token)
+            }
+            None => self.error("Unexpected end of source".to_string()),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AssemblyError> {
+        let token = self.next()?;
+        match token.text == text {
+            true => Ok(()),
+            false => self.error(format!("Expected {}, got {}", text, token.text)),
+        }
+    }
+
+    fn here(&self) -> u16 {
+        PROGRAM_START + self.bytes.len() as u16
+    }
+
+    fn emit(&mut self, opcode: u16) {
+        self.bytes.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    fn patch(&mut self, offset: usize, address: u16) {
+        self.bytes[offset] = (self.bytes[offset] & 0xF0) | ((address >> 8) as u8 & 0x0F);
+        self.bytes[offset + 1] = address as u8;
+    }
+
+    fn register_of(&self, text: &str) -> Option<u8> {
+        parse_register(text).or_else(|| self.aliases.get(text).copied())
+    }
+
+    fn register(&mut self) -> Result<u16, AssemblyError> {
+        let token = self.next()?;
+        match self.register_of(token.text) {
+            Some(register) => Ok(register as u16),
+            None => self.error(format!("Expected a register, got {}", token.text)),
+        }
+    }
+
+    fn value_of(&self, text: &str) -> Option<i32> {
+        parse_number(text)
+            .or_else(|| self.constants.get(text).copied())
+            .or_else(|| self.labels.get(text).map(|&address| address as i32))
+    }
+
+    //A number, constant or label defined further up, checked against a range.
+    fn value(&mut self, min: i32, max: i32) -> Result<i32, AssemblyError> {
+        let token = self.next()?;
+        match self.value_of(token.text) {
+            Some(value) if value >= min && value <= max => Ok(value),
+            Some(value) => self.error(format!("{} does not fit in {}..{}", value, min, max)),
+            None if self.is_name(token.text) => Undefined {
+                line: token.line,
+                name: token.text,
+            }
+            .fail(),
+            None => self.error(format!("Expected a value, got {}", token.text)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u16, AssemblyError> {
+        Ok(self.value(-128, 255)? as u16 & 0xFF)
+    }
+
+    fn nibble(&mut self) -> Result<u16, AssemblyError> {
+        Ok(self.value(0, 15)? as u16)
+    }
+
+    fn is_name(&self, text: &str) -> bool {
+        let mut chars = text.chars();
+        chars
+            .next()
+            .is_some_and(|first| first.is_alphabetic() || first == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    }
+
+    fn name(&mut self) -> Result<&'a str, AssemblyError> {
+        let token = self.next()?;
+        let taken = self.labels.contains_key(token.text)
+            || self.constants.contains_key(token.text)
+            || self.aliases.contains_key(token.text);
+        match self.is_name(token.text) && parse_register(token.text).is_none() {
+            true if taken => self.error(format!("{} is already defined", token.text)),
+            true => Ok(token.text),
+            false => self.error(format!("{} can not be used as a name", token.text)),
+        }
+    }
+
+    //An instruction with a 12 bit address, labels can be defined further down.
+    fn emit_reference(&mut self, opcode: u16, token: Token) -> Result<(), AssemblyError> {
+        match self.value_of(token.text) {
+            Some(address) if (0..=0xFFF).contains(&address) => {
+                self.emit(opcode | address as u16);
+            }
+            Some(address) => return self.error(format!("Address {} is out of range", address)),
+            None if self.is_name(token.text) => {
+                self.fixups.push(Fixup {
+                    offset: self.bytes.len(),
+                    name: token.text.to_string(),
+                    line: token.line,
+                });
+                self.emit(opcode);
+            }
+            None => return self.error(format!("Expected an address, got {}", token.text)),
+        }
+        Ok(())
+    }
+
+    fn emit_address(&mut self, opcode: u16) -> Result<(), AssemblyError> {
+        let token = self.next()?;
+        self.emit_reference(opcode, token)
+    }
+
+    fn statement(&mut self) -> Result<(), AssemblyError> {
+        let token = self.next()?;
+        match token.text {
+            ":" => {
+                let name = self.name()?;
+                let address = self.here();
+                self.labels.insert(name.to_string(), address);
+                self.symbols.insert(address, name);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value(-0x8000, 0xFFFF)?;
+                self.constants.insert(name.to_string(), value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name.to_string(), register as u8);
+            }
+            ":org" => {
+                let address = self.value(PROGRAM_START as i32, 0xFFF)? as u16;
+                if address < self.here() {
+                    return self.error(format!(":org {:03X} is behind the code", address));
+                }
+                self.bytes.resize((address - PROGRAM_START) as usize, 0);
+            }
+            ":call" => self.emit_address(0x2000)?,
+            ":breakpoint" => {
+                self.next()?;
+            }
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n)
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n)
+            }
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "audio" => self.emit(0xF002),
+            "jump" => self.emit_address(0x1000)?,
+            "jump0" => self.emit_address(0xB000)?,
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | n << 8)
+            }
+            "i" => self.i_statement()?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let low = match token.text {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.emit(0xF000 | x << 8 | low)
+            }
+            "bcd" | "save" | "load" | "saveflags" | "loadflags" => {
+                let x = self.register()?;
+                let low = match token.text {
+                    "bcd" => 0x33,
+                    "save" => 0x55,
+                    "load" => 0x65,
+                    "saveflags" => 0x75,
+                    _ => 0x85,
+                };
+                self.emit(0xF000 | x << 8 | low)
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n)
+            }
+            "if" => self.if_statement()?,
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump, line }) => {
+                    let exit = self.bytes.len();
+                    self.emit(0x1000);
+                    let here = self.here();
+                    self.patch(jump, here);
+                    self.blocks.push(Block::Else { jump: exit, line });
+                }
+                _ => return self.error("else without if ... begin".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) | Some(Block::Else { jump, .. }) => {
+                    let here = self.here();
+                    self.patch(jump, here);
+                }
+                _ => return self.error("end without if ... begin".to_string()),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here(),
+                exits: Vec::new(),
+                line: token.line,
+            }),
+            "while" => {
+                let condition = self.condition()?;
+                self.emit(condition.skip_if_true);
+                let exit = self.bytes.len();
+                self.emit(0x1000);
+                let innermost = self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                });
+                match innermost {
+                    Some(exits) => exits.push(exit),
+                    None => return self.error("while outside of a loop".to_string()),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits, .. }) => {
+                    self.emit(0x1000 | start);
+                    let here = self.here();
+                    for exit in exits {
+                        self.patch(exit, here);
+                    }
+                }
+                _ => return self.error("again without loop".to_string()),
+            },
+            text => match self.register_of(text) {
+                Some(x) => self.register_statement(x as u16)?,
+                None => match parse_number(text).or_else(|| self.constants.get(text).copied()) {
+                    Some(value) if (-128..=255).contains(&value) => self.bytes.push(value as u8),
+                    Some(value) => return self.error(format!("{} does not fit in a byte", value)),
+                    //A bare label is a subroutine call.
+                    None => self.emit_reference(0x2000, token)?,
+                },
+            },
+        }
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<(), AssemblyError> {
+        let operator = self.next()?;
+        match operator.text {
+            ":=" => {
+                let token = self.next()?;
+                match token.text {
+                    "hex" => {
+                        let x = self.register()?;
+                        self.emit(0xF029 | x << 8)
+                    }
+                    "bighex" => {
+                        let x = self.register()?;
+                        self.emit(0xF030 | x << 8)
+                    }
+                    _ => self.emit_reference(0xA000, token)?,
+                }
+            }
+            "+=" => {
+                let x = self.register()?;
+                self.emit(0xF01E | x << 8)
+            }
+            text => return self.error(format!("Unknown operator i {}", text)),
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u16) -> Result<(), AssemblyError> {
+        let operator = self.next()?.text;
+        let source = self.next()?;
+        if let Some(y) = self.register_of(source.text) {
+            let low = match operator {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return self.error(format!("Unknown operator {}", operator)),
+            };
+            self.emit(0x8000 | x << 8 | (y as u16) << 4 | low);
+            return Ok(());
+        }
+        //Put the value back so byte() can read it.
+        self.position -= 1;
+        match (operator, source.text) {
+            (":=", "random") => {
+                self.next()?;
+                let mask = self.byte()?;
+                self.emit(0xC000 | x << 8 | mask)
+            }
+            (":=", "delay") => {
+                self.next()?;
+                self.emit(0xF007 | x << 8)
+            }
+            (":=", "key") => {
+                self.next()?;
+                self.emit(0xF00A | x << 8)
+            }
+            (":=", _) => {
+                let value = self.byte()?;
+                self.emit(0x6000 | x << 8 | value)
+            }
+            ("+=", _) => {
+                let value = self.byte()?;
+                self.emit(0x7000 | x << 8 | value)
+            }
+            ("-=", _) => {
+                let value = self.byte()?;
+                self.emit(0x7000 | x << 8 | ((0x100 - value) & 0xFF))
+            }
+            _ => return self.error(format!("Expected a register after {}", operator)),
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AssemblyError> {
+        let x = self.register()?;
+        let operator = self.next()?.text;
+        let (true_skip, false_skip) = match operator {
+            "==" | "!=" => {
+                let token = self.next()?;
+                let (equal, not_equal) = match self.register_of(token.text) {
+                    Some(y) => {
+                        let y = y as u16;
+                        (0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4)
+                    }
+                    None => {
+                        self.position -= 1;
+                        let value = self.byte()?;
+                        (0x3000 | x << 8 | value, 0x4000 | x << 8 | value)
+                    }
+                };
+                match operator {
+                    "==" => (equal, not_equal),
+                    _ => (not_equal, equal),
+                }
+            }
+            "key" => (0xE09E | x << 8, 0xE0A1 | x << 8),
+            "-key" => (0xE0A1 | x << 8, 0xE09E | x << 8),
+            _ => return self.error(format!("Unsupported comparison {}", operator)),
+        };
+        Ok(Condition {
+            skip_if_true: true_skip,
+            skip_if_false: false_skip,
+        })
+    }
+
+    fn if_statement(&mut self) -> Result<(), AssemblyError> {
+        let line = self.line();
+        let condition = self.condition()?;
+        let token = self.next()?;
+        match token.text {
+            "then" => self.emit(condition.skip_if_false),
+            "begin" => {
+                self.emit(condition.skip_if_true);
+                let jump = self.bytes.len();
+                self.emit(0x1000);
+                self.blocks.push(Block::If { jump, line });
+            }
+            text => return self.error(format!("Expected then or begin, got {}", text)),
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Program, AssemblyError> {
+        if let Some(block) = self.blocks.last() {
+            let (block, line) = match block {
+                Block::If { line, .. } | Block::Else { line, .. } => ("begin", *line),
+                Block::Loop { line, .. } => ("loop", *line),
+            };
+            return Unclosed { line, block }.fail();
+        }
+        let main = match self.labels.get("main") {
+            Some(&main) => main,
+            None => return NoMain.fail(),
+        };
+        self.patch(0, main);
+        for fixup in std::mem::take(&mut self.fixups) {
+            match self.labels.get(&fixup.name) {
+                Some(&address) => self.patch(fixup.offset, address),
+                None => {
+                    return Undefined {
+                        line: fixup.line,
+                        name: fixup.name,
+                    }
+                    .fail()
+                }
+            }
+        }
+        Ok(Program {
+            bytes: self.bytes,
+            symbols: self.symbols,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::octo::{assemble, AssemblyError};
+
+    fn words(source: &str) -> Vec<u16> {
+        assemble(source)
+            .unwrap()
+            .bytes
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn statements_test() {
+        let source = "
+            :const SPEED 3
+            :alias x v4
+            : main # comment
+              clear
+              x := SPEED
+              v1 += -1
+              v2 := random 0xFF
+              i := ball
+              sprite x v1 4
+              vf <<= v3
+              draw
+              jump main
+            : draw
+              return
+            : ball
+              0b11110000 0x90
+        ";
+        assert_eq!(
+            words(source),
+            [
+                0x1202, 0x00E0, 0x6403, 0x71FF, 0xC2FF, 0xA216, 0xD414, 0x8F3E, 0x2214, 0x1202,
+                0x00EE, 0xF090
+            ]
+        );
+        let symbols = assemble(source).unwrap().symbols;
+        assert_eq!(symbols.name(0x202), Some("main"));
+        assert_eq!(symbols.name(0x216), Some("ball"));
+    }
+
+    #[test]
+    fn control_flow_test() {
+        let source = "
+            : main
+              if v0 == 1 then v1 := 2
+              loop
+                while v2 != v3
+                if v0 key begin
+                  v0 += 1
+                else
+                  v0 -= 1
+                end
+              again
+        ";
+        assert_eq!(
+            words(source),
+            [
+                0x1202, 0x4001, 0x6102, 0x9230, 0x1216, 0xE09E, 0x1212, 0x7001, 0x1214, 0x70FF,
+                0x1206
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_test() {
+        assert!(matches!(
+            assemble(": main\n  jump nowhere"),
+            Err(AssemblyError::Undefined { line: 2, .. })
+        ));
+        assert!(matches!(
+            assemble(": start clear"),
+            Err(AssemblyError::NoMain)
+        ));
+        assert!(matches!(
+            assemble(": main\n  loop\n  v0 += 1"),
+            Err(AssemblyError::Unclosed { line: 2, .. })
+        ));
+        assert!(matches!(
+            assemble(": main\n  v0 := 300"),
+            Err(AssemblyError::SyntaxError { line: 2, .. })
+        ));
+        assert!(matches!(
+            assemble(": main\n: main"),
+            Err(AssemblyError::SyntaxError { .. })
+        ));
+    }
+}
@@ -0,0 +1,294 @@
+use chip8forever::font::SCHIP_FONT;
+use chip8forever::palette::Palette;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+use std::time::{Duration, Instant};
+
+//Glyphs are 4x5 like the CHIP-8 font, drawn in a 5x6 cell.
+const GLYPH_WIDTH: i32 = 4;
+const GLYPH_HEIGHT: i32 = 5;
+const CELL_WIDTH: i32 = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: i32 = GLYPH_HEIGHT + 1;
+const MARGIN: i32 = 2;
+
+const MESSAGE_TIME: Duration = Duration::from_secs(3);
+const FAULT_COLOR: Color = Color {
+    r: 0xFF,
+    g: 0x40,
+    b: 0x40,
+    a: 0xFF,
+};
+
+//Letters and punctuation missing from the hex font.
+const EXTRA_GLYPHS: [(char, [u8; 5]); 27] = [
+    ('G', [0xF0, 0x80, 0xB0, 0x90, 0xF0]),
+    ('H', [0x90, 0x90, 0xF0, 0x90, 0x90]),
+    ('I', [0xE0, 0x40, 0x40, 0x40, 0xE0]),
+    ('J', [0x70, 0x20, 0x20, 0xA0, 0xE0]),
+    ('K', [0x90, 0xA0, 0xC0, 0xA0, 0x90]),
+    ('L', [0x80, 0x80, 0x80, 0x80, 0xF0]),
+    ('M', [0x90, 0xF0, 0xF0, 0x90, 0x90]),
+    ('N', [0x90, 0xD0, 0xB0, 0x90, 0x90]),
+    ('O', [0x60, 0x90, 0x90, 0x90, 0x60]),
+    ('P', [0xE0, 0x90, 0xE0, 0x80, 0x80]),
+    ('Q', [0x60, 0x90, 0x90, 0xB0, 0x70]),
+    ('R', [0xE0, 0x90, 0xE0, 0xA0, 0x90]),
+    ('S', [0x70, 0x80, 0x60, 0x10, 0xE0]),
+    ('T', [0xE0, 0x40, 0x40, 0x40, 0x40]),
+    ('U', [0x90, 0x90, 0x90, 0x90, 0xF0]),
+    ('V', [0x90, 0x90, 0x90, 0xA0, 0x40]),
+    ('W', [0x90, 0x90, 0xF0, 0xF0, 0x90]),
+    ('X', [0x90, 0x90, 0x60, 0x90, 0x90]),
+    ('Y', [0xA0, 0xA0, 0x40, 0x40, 0x40]),
+    ('Z', [0xF0, 0x10, 0x60, 0x80, 0xF0]),
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x40]),
+    (':', [0x00, 0x40, 0x00, 0x40, 0x00]),
+    ('-', [0x00, 0x00, 0xF0, 0x00, 0x00]),
+    ('/', [0x10, 0x10, 0x20, 0x40, 0x80]),
+    ('>', [0x80, 0x40, 0x20, 0x40, 0x80]),
+    ('!', [0x40, 0x40, 0x40, 0x00, 0x40]),
+];
+
+const UNKNOWN_GLYPH: [u8; 5] = [0xE0, 0x10, 0x60, 0x00, 0x40];
+
+//Bitmap for a character, case insensitive. Unknown characters become a question mark.
+fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    if let Some(digit) = c.to_digit(16) {
+        let start = digit as usize * 5;
+        let mut glyph = [0; 5];
+        glyph.copy_from_slice(&SCHIP_FONT[start..start + 5]);
+        return glyph;
+    }
+    EXTRA_GLYPHS
+        .iter()
+        .find(|(glyph_char, _)| *glyph_char == c)
+        .map(|(_, glyph)| *glyph)
+        .unwrap_or(UNKNOWN_GLYPH)
+}
+
+//Size of a line of text in unscaled pixels, including the trailing spacing.
+fn text_size(text: &str) -> (i32, i32) {
+    (text.chars().count() as i32 * CELL_WIDTH, CELL_HEIGHT)
+}
+
+//Counts frames and instructions and turns them into per second rates.
+pub struct RateCounter {
+    since: Instant,
+    frames: u32,
+    instructions: u64,
+    fps: f64,
+    ips: f64,
+}
+
+impl RateCounter {
+    pub fn new() -> RateCounter {
+        RateCounter {
+            since: Instant::now(),
+            frames: 0,
+            instructions: 0,
+            fps: 0.0,
+            ips: 0.0,
+        }
+    }
+
+    //Rates are refreshed once a second.
+    pub fn frame(&mut self, instructions: u32) {
+        self.frames += 1;
+        self.instructions += instructions as u64;
+        let elapsed = self.since.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let seconds = elapsed.as_secs_f64();
+            self.fps = self.frames as f64 / seconds;
+            self.ips = self.instructions as f64 / seconds;
+            self.since = Instant::now();
+            self.frames = 0;
+            self.instructions = 0;
+        }
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    pub fn ips(&self) -> f64 {
+        self.ips
+    }
+}
+
+//Text drawn over the emulator screen: stats and run state top left, messages bottom left.
+pub struct Osd {
+    show_stats: bool,
+    stats: String,
+    state: Option<String>,
+    messages: Vec<(String, Instant)>,
+    fault: Option<String>,
+}
+
+impl Osd {
+    pub fn new() -> Osd {
+        Osd {
+            show_stats: false,
+            stats: String::new(),
+            state: None,
+            messages: Vec::new(),
+            fault: None,
+        }
+    }
+
+    pub fn toggle_stats(&mut self) {
+        self.show_stats = !self.show_stats;
+    }
+
+    pub fn set_stats(&mut self, rates: &RateCounter) {
+        self.stats = format!("{:.0} FPS {:.0} IPS", rates.fps(), rates.ips());
+    }
+
+    //Run state like paused or fast-forward, None while running normally.
+    pub fn set_state(&mut self, state: Option<&str>) {
+        self.state = state.map(String::from);
+    }
+
+    //Shown for a few seconds.
+    pub fn message(&mut self, text: &str) {
+        self.messages.push((text.to_string(), Instant::now()));
+    }
+
+    //Stays on screen until cleared.
+    pub fn set_fault(&mut self, fault: Option<String>) {
+        self.fault = fault;
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.messages
+            .retain(|(_, shown)| now.duration_since(*shown) < MESSAGE_TIME);
+    }
+
+    pub fn draw(&mut self, canvas: &mut WindowCanvas, palette: &Palette) {
+        self.expire(Instant::now());
+        let height = match canvas.output_size() {
+            Ok((_, height)) => height,
+            Err(_) => return,
+        };
+        let scale = std::cmp::max(1, height as i32 / 160);
+        let (r, g, b) = palette.foreground;
+        let text_color = Color::RGB(r, g, b);
+        let (r, g, b) = palette.background;
+        let box_color = Color::RGBA(r, g, b, 0xC0);
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        let mut top = Vec::new();
+        if self.show_stats {
+            top.push(self.stats.as_str());
+        }
+        if let Some(state) = &self.state {
+            top.push(state.as_str());
+        }
+        for (i, line) in top.iter().enumerate() {
+            let y = (MARGIN + i as i32 * (CELL_HEIGHT + MARGIN)) * scale;
+            draw_text(
+                canvas,
+                line,
+                MARGIN * scale,
+                y,
+                scale,
+                text_color,
+                box_color,
+            );
+        }
+
+        let mut bottom: Vec<(&str, Color)> = self
+            .messages
+            .iter()
+            .map(|(text, _)| (text.as_str(), text_color))
+            .collect();
+        if let Some(fault) = &self.fault {
+            bottom.push((fault.as_str(), FAULT_COLOR));
+        }
+        for (i, (line, color)) in bottom.iter().rev().enumerate() {
+            let y = height as i32 - (i as i32 + 1) * (CELL_HEIGHT + MARGIN) * scale;
+            draw_text(canvas, line, MARGIN * scale, y, scale, *color, box_color);
+        }
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
+
+fn draw_text(
+    canvas: &mut WindowCanvas,
+    text: &str,
+    x: i32,
+    y: i32,
+    scale: i32,
+    color: Color,
+    box_color: Color,
+) {
+    let (width, height) = text_size(text);
+    canvas.set_draw_color(box_color);
+    let _ = canvas.fill_rect(Rect::new(
+        x - scale,
+        y - scale,
+        ((width + 1) * scale) as u32,
+        ((height + 1) * scale) as u32,
+    ));
+
+    let mut rects = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as i32 * CELL_WIDTH * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (0x80 >> column) != 0 {
+                    rects.push(Rect::new(
+                        left + column * scale,
+                        y + row as i32 * scale,
+                        scale as u32,
+                        scale as u32,
+                    ));
+                }
+            }
+        }
+    }
+    canvas.set_draw_color(color);
+    let _ = canvas.fill_rects(&rects);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::osd::{glyph, text_size, Osd, MESSAGE_TIME, UNKNOWN_GLYPH};
+    use chip8forever::font::SCHIP_FONT;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn glyph_test() {
+        assert_eq!(glyph('0'), [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        assert_eq!(&glyph('F')[..], &SCHIP_FONT[75..80]);
+        assert_eq!(glyph('b'), glyph('B'));
+        assert_eq!(glyph('s'), glyph('S'));
+        assert_eq!(glyph('~'), UNKNOWN_GLYPH);
+    }
+
+    #[test]
+    fn every_letter_has_glyph_test() {
+        for c in (b'A'..=b'Z').map(char::from) {
+            assert_ne!(glyph(c), UNKNOWN_GLYPH, "{}", c);
+        }
+    }
+
+    #[test]
+    fn text_size_test() {
+        assert_eq!(text_size("60 FPS"), (30, 6));
+        assert_eq!(text_size(""), (0, 6));
+    }
+
+    #[test]
+    fn message_expire_test() {
+        let mut osd = Osd::new();
+        osd.message("Saved");
+        let now = Instant::now();
+        osd.expire(now);
+        assert_eq!(osd.messages.len(), 1);
+        osd.expire(now + MESSAGE_TIME + Duration::from_millis(1));
+        assert!(osd.messages.is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::framebuffer::PixelBuffer;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+pub type Rgb = (u8, u8, u8);
+
+//Colours used for lit and unlit pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub foreground: Rgb,
+    pub background: Rgb,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            foreground: (255, 255, 255),
+            background: (0, 0, 0),
+        }
+    }
+}
+
+impl Palette {
+    pub fn color(&self, lit: bool) -> Rgb {
+        match lit {
+            true => self.foreground,
+            false => self.background,
+        }
+    }
+
+    //Mix between background (0.0) and foreground (1.0).
+    pub fn blend(&self, intensity: f32) -> Rgb {
+        let mix = |bg: u8, fg: u8| (bg as f32 + (fg as f32 - bg as f32) * intensity).round() as u8;
+        let (fg, bg) = (self.foreground, self.background);
+        (mix(bg.0, fg.0), mix(bg.1, fg.1), mix(bg.2, fg.2))
+    }
+
+    //Draw the pixel buffer as an RGB image, every pixel a scale x scale square.
+    pub fn render(&self, pixels: &PixelBuffer, scale: u32) -> RgbImage {
+        let scale = scale.max(1) as usize;
+        let width = pixels.columns() * scale;
+        let height = pixels.rows() * scale;
+        let mut data = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let (r, g, b) = self.color(pixels.get(x / scale, y / scale));
+                data.extend_from_slice(&[r, g, b]);
+            }
+        }
+        RgbImage {
+            width: width as u32,
+            height: height as u32,
+            data,
+        }
+    }
+}
+
+fn parse_rgb(s: &str) -> Option<Rgb> {
+    let s = s.trim_start_matches('#');
+    if s.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(s, 16).ok()?;
+    Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+//Either a preset name or two hex colours: "foreground,background".
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (foreground, background) = match s {
+            "classic" => return Ok(Palette::default()),
+            "amber" => ((255, 176, 0), (26, 16, 0)),
+            "green" => ((51, 255, 51), (10, 26, 10)),
+            "lcd" => ((15, 56, 15), (155, 188, 15)),
+            "octo" => ((255, 204, 0), (153, 102, 0)),
+            _ => {
+                let mut colors = s.split(',').map(parse_rgb);
+                match (colors.next(), colors.next(), colors.next()) {
+                    (Some(Some(fg)), Some(Some(bg)), None) => (fg, bg),
+                    _ => {
+                        return Err(format!(
+                        "Unknown palette {}, use classic, amber, green, lcd, octo or RRGGBB,RRGGBB",
+                        s
+                    ))
+                    }
+                }
+            }
+        };
+        Ok(Palette {
+            foreground,
+            background,
+        })
+    }
+}
+
+//Always the RRGGBB,RRGGBB form, which parses back to the same palette.
+impl Display for Palette {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (fg, bg) = (self.foreground, self.background);
+        write!(
+            f,
+            "{:02X}{:02X}{:02X},{:02X}{:02X}{:02X}",
+            fg.0, fg.1, fg.2, bg.0, bg.1, bg.2
+        )
+    }
+}
+
+//Packed 24-bit RGB pixels, row by row.
+pub struct RgbImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::framebuffer::{PixelBuffer, Sprite};
+    use crate::palette::Palette;
+
+    #[test]
+    fn parse_test() {
+        let palette: Palette = "#102030,405060".parse().unwrap();
+        assert_eq!(palette.foreground, (0x10, 0x20, 0x30));
+        assert_eq!(palette.background, (0x40, 0x50, 0x60));
+        assert!("nope".parse::<Palette>().is_err());
+        assert!("102030".parse::<Palette>().is_err());
+        let amber: Palette = "amber".parse().unwrap();
+        assert_eq!(amber.to_string(), "FFB000,1A1000");
+        assert_eq!(amber.to_string().parse::<Palette>().unwrap(), amber);
+    }
+
+    #[test]
+    fn render_test() {
+        let mut pixels = PixelBuffer::new(2, 1);
+        pixels.add_sprite(0, 0, Sprite::new(&[0x80]));
+        let image = Palette::default().render(&pixels, 2);
+        assert_eq!((image.width, image.height), (4, 2));
+        let row = [255, 255, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0];
+        assert_eq!(&image.data[..12], &row);
+        assert_eq!(&image.data[12..], &row);
+    }
+}
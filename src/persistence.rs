@@ -0,0 +1,109 @@
+use crate::framebuffer::PixelBuffer;
+use crate::palette::{Palette, RgbImage};
+
+//Phosphor-like afterglow: pixels that switch off fade out over a few frames,
+//which hides the flicker of games that erase and redraw their sprites.
+pub struct Persistence {
+    decay: f32,
+    glow: Vec<f32>,
+    columns: usize,
+    rows: usize,
+}
+
+impl Persistence {
+    //Decay is the brightness an unlit pixel keeps each frame, 0 turns the filter off.
+    pub fn new(decay: f32) -> Persistence {
+        Persistence {
+            //Not a clamp, NaN has to end up as 0 too.
+            decay: if decay > 0.0 { decay.min(0.99) } else { 0.0 },
+            glow: Vec::new(),
+            columns: 0,
+            rows: 0,
+        }
+    }
+
+    //Feed the next 60Hz frame; a buffer of another size starts from a dark screen.
+    pub fn update(&mut self, pixels: &PixelBuffer) {
+        if self.columns != pixels.columns() || self.rows != pixels.rows() {
+            self.columns = pixels.columns();
+            self.rows = pixels.rows();
+            self.glow = vec![0.0; self.columns * self.rows];
+        }
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let glow = &mut self.glow[row * self.columns + column];
+                *glow = match pixels.get(column, row) {
+                    true => 1.0,
+                    false => *glow * self.decay,
+                };
+            }
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    //Brightness between 0 (background) and 1 (foreground).
+    pub fn intensity(&self, column: usize, row: usize) -> f32 {
+        if column >= self.columns || row >= self.rows {
+            return 0.0;
+        }
+        self.glow[row * self.columns + column]
+    }
+
+    //Same as Palette::render, with faded pixels blended towards the background.
+    pub fn render(&self, palette: &Palette, scale: u32) -> RgbImage {
+        let scale = scale.max(1) as usize;
+        let width = self.columns * scale;
+        let height = self.rows * scale;
+        let mut data = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let (r, g, b) = palette.blend(self.intensity(x / scale, y / scale));
+                data.extend_from_slice(&[r, g, b]);
+            }
+        }
+        RgbImage {
+            width: width as u32,
+            height: height as u32,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::framebuffer::{PixelBuffer, Sprite};
+    use crate::persistence::Persistence;
+
+    #[test]
+    fn fade_test() {
+        let mut pixels = PixelBuffer::new(8, 1);
+        let mut persistence = Persistence::new(0.5);
+        pixels.add_sprite(0, 0, Sprite::new(&[0x80]));
+        persistence.update(&pixels);
+        assert_eq!(persistence.intensity(0, 0), 1.0);
+        pixels.clear();
+        persistence.update(&pixels);
+        assert_eq!(persistence.intensity(0, 0), 0.5);
+        persistence.update(&pixels);
+        assert_eq!(persistence.intensity(0, 0), 0.25);
+        assert_eq!(persistence.intensity(1, 0), 0.0);
+    }
+
+    #[test]
+    fn disabled_test() {
+        let mut pixels = PixelBuffer::new(8, 1);
+        let mut persistence = Persistence::new(0.0);
+        pixels.add_sprite(0, 0, Sprite::new(&[0x80]));
+        persistence.update(&pixels);
+        pixels.clear();
+        persistence.update(&pixels);
+        assert_eq!(persistence.intensity(0, 0), 0.0);
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+//What FX55 and FX65 leave in I afterwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryIncrement {
+    //I = I + X + 1, COSMAC VIP.
+    XPlusOne,
+    //I = I + X, CHIP-48.
+    X,
+    //I is left alone, SUPER-CHIP.
+    Unchanged,
+}
+
+//Behaviour that differs between CHIP-8 interpreters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    //8XY6/8XYE shift VY into VX; otherwise VX is shifted in place.
+    pub shift_vy: bool,
+    pub memory_increment: MemoryIncrement,
+    //8XY1/8XY2/8XY3 clear VF.
+    pub vf_reset: bool,
+    //BNNN jumps to NNN + VX (X being the top nibble of NNN) instead of NNN + V0.
+    pub jump_vx: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::vip()
+    }
+}
+
+impl Quirks {
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            memory_increment: MemoryIncrement::XPlusOne,
+            vf_reset: true,
+            jump_vx: false,
+        }
+    }
+
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            memory_increment: MemoryIncrement::X,
+            vf_reset: false,
+            jump_vx: true,
+        }
+    }
+
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            memory_increment: MemoryIncrement::Unchanged,
+            vf_reset: false,
+            jump_vx: true,
+        }
+    }
+
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            memory_increment: MemoryIncrement::XPlusOne,
+            vf_reset: false,
+            jump_vx: false,
+        }
+    }
+}
+
+impl FromStr for MemoryIncrement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x+1" => Ok(MemoryIncrement::XPlusOne),
+            "x" => Ok(MemoryIncrement::X),
+            "unchanged" => Ok(MemoryIncrement::Unchanged),
+            _ => Err(format!(
+                "Unknown memory increment {}, use x+1, x or unchanged",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for MemoryIncrement {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            MemoryIncrement::XPlusOne => "x+1",
+            MemoryIncrement::X => "x",
+            MemoryIncrement::Unchanged => "unchanged",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//One quirk changed on top of a preset, NAME=VALUE like shift-vy=off or memory-increment=x.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuirkOverride {
+    ShiftVy(bool),
+    MemoryIncrement(MemoryIncrement),
+    VfReset(bool),
+    JumpVx(bool),
+}
+
+impl QuirkOverride {
+    pub fn apply(self, quirks: &mut Quirks) {
+        match self {
+            QuirkOverride::ShiftVy(on) => quirks.shift_vy = on,
+            QuirkOverride::MemoryIncrement(increment) => quirks.memory_increment = increment,
+            QuirkOverride::VfReset(on) => quirks.vf_reset = on,
+            QuirkOverride::JumpVx(on) => quirks.jump_vx = on,
+        }
+    }
+}
+
+impl Quirks {
+    //Overrides that turn any preset into these quirks.
+    pub fn overrides(&self) -> Vec<QuirkOverride> {
+        vec![
+            QuirkOverride::ShiftVy(self.shift_vy),
+            QuirkOverride::MemoryIncrement(self.memory_increment),
+            QuirkOverride::VfReset(self.vf_reset),
+            QuirkOverride::JumpVx(self.jump_vx),
+        ]
+    }
+}
+
+impl Display for QuirkOverride {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let switch = |on: bool| if on { "on" } else { "off" };
+        match self {
+            QuirkOverride::ShiftVy(on) => write!(f, "shift-vy={}", switch(*on)),
+            QuirkOverride::MemoryIncrement(increment) => {
+                write!(f, "memory-increment={}", increment)
+            }
+            QuirkOverride::VfReset(on) => write!(f, "vf-reset={}", switch(*on)),
+            QuirkOverride::JumpVx(on) => write!(f, "jump-vx={}", switch(*on)),
+        }
+    }
+}
+
+fn parse_switch(s: &str) -> Result<bool, String> {
+    match s {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err(format!("Expected on or off, got {}", s)),
+    }
+}
+
+impl FromStr for QuirkOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let value = parts
+            .next()
+            .ok_or_else(|| format!("Expected NAME=VALUE, got {}", s))?;
+        match name {
+            "shift-vy" => parse_switch(value).map(QuirkOverride::ShiftVy),
+            "memory-increment" => value.parse().map(QuirkOverride::MemoryIncrement),
+            "vf-reset" => parse_switch(value).map(QuirkOverride::VfReset),
+            "jump-vx" => parse_switch(value).map(QuirkOverride::JumpVx),
+            _ => Err(format!(
+                "Unknown quirk {}, use shift-vy, memory-increment, vf-reset or jump-vx",
+                name
+            )),
+        }
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vip" => Ok(Quirks::vip()),
+            "chip48" => Ok(Quirks::chip48()),
+            "schip" => Ok(Quirks::schip()),
+            "xochip" => Ok(Quirks::xochip()),
+            _ => Err(format!(
+                "Unknown quirks preset {}, use vip, chip48, schip or xochip",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::quirks::{MemoryIncrement, QuirkOverride, Quirks};
+
+    #[test]
+    fn quirk_override_test() {
+        let mut quirks = Quirks::vip();
+        "shift-vy=off"
+            .parse::<QuirkOverride>()
+            .unwrap()
+            .apply(&mut quirks);
+        "memory-increment=unchanged"
+            .parse::<QuirkOverride>()
+            .unwrap()
+            .apply(&mut quirks);
+        assert!(!quirks.shift_vy);
+        assert_eq!(quirks.memory_increment, MemoryIncrement::Unchanged);
+        assert!(quirks.vf_reset);
+    }
+
+    #[test]
+    fn overrides_round_trip_test() {
+        let mut quirks = Quirks::vip();
+        for quirk in Quirks::schip().overrides() {
+            let parsed: QuirkOverride = quirk.to_string().parse().unwrap();
+            parsed.apply(&mut quirks);
+        }
+        assert_eq!(quirks, Quirks::schip());
+    }
+
+    #[test]
+    fn quirk_override_error_test() {
+        assert!("shift-vy".parse::<QuirkOverride>().is_err());
+        assert!("shift-vy=maybe".parse::<QuirkOverride>().is_err());
+        assert!("wrap-sprites=on".parse::<QuirkOverride>().is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use crate::framebuffer::PixelBuffer;
+use crate::palette::Palette;
+use crate::persistence::Persistence;
+use snafu::{ResultExt, Snafu};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+//Shades between background and foreground used for faded pixels in GIFs.
+const GIF_LEVELS: usize = 16;
+
+#[derive(Debug, Snafu)]
+pub enum RecordError {
+    #[snafu(display("Could not create recording {}: {}", filename.display(), source))]
+    CreateError {
+        filename: PathBuf,
+        source: io::Error,
+    },
+    #[snafu(display("Could not write recording: {}", source))]
+    WriteError { source: io::Error },
+    #[snafu(display("Could not encode GIF: {}", source))]
+    GifError { source: gif::EncodingError },
+    #[snafu(display("Recording of {}x{} is too large for a GIF", width, height))]
+    GifSizeError { width: usize, height: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordFormat {
+    //Animated GIF, loops forever.
+    Gif,
+    //Uncompressed YUV 4:4:4 stream that ffmpeg and friends read directly.
+    Y4m,
+    //Bare RGB24 frames, size and rate have to be told to the consumer.
+    Raw,
+}
+
+impl RecordFormat {
+    //Picked from the extension: .gif, .y4m, anything else is raw. "-" streams Y4M to stdout.
+    pub fn from_path(path: &Path) -> RecordFormat {
+        if path == Path::new("-") {
+            return RecordFormat::Y4m;
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gif") => RecordFormat::Gif,
+            Some("y4m") => RecordFormat::Y4m,
+            _ => RecordFormat::Raw,
+        }
+    }
+}
+
+enum Sink {
+    //Nothing written yet, the first frame decides the size.
+    Pending(Box<dyn Write>),
+    Gif(gif::Encoder<Box<dyn Write>>),
+    Stream(Box<dyn Write>),
+}
+
+//Records 60 fps gameplay, one push_frame per emulated frame.
+pub struct Recorder {
+    format: RecordFormat,
+    sink: Option<Sink>,
+    palette: Palette,
+    persistence: Persistence,
+    scale: usize,
+    size: Option<(usize, usize)>,
+    frames: u64,
+}
+
+impl Recorder {
+    pub fn create<T: AsRef<Path>>(
+        path: T,
+        palette: Palette,
+        persistence: f32,
+        scale: u32,
+    ) -> Result<Recorder, RecordError> {
+        let filename = path.as_ref();
+        let writer: Box<dyn Write> = if filename == Path::new("-") {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            let file = File::create(filename).context(CreateError {
+                filename: filename.to_path_buf(),
+            })?;
+            Box::new(BufWriter::new(file))
+        };
+        Ok(Recorder::new(
+            writer,
+            RecordFormat::from_path(filename),
+            palette,
+            persistence,
+            scale,
+        ))
+    }
+
+    //Records into any writer, create picks the format from the file name.
+    pub fn new(
+        writer: Box<dyn Write>,
+        format: RecordFormat,
+        palette: Palette,
+        persistence: f32,
+        scale: u32,
+    ) -> Recorder {
+        Recorder {
+            format,
+            sink: Some(Sink::Pending(writer)),
+            palette,
+            persistence: Persistence::new(persistence),
+            scale: scale.max(1) as usize,
+            size: None,
+            frames: 0,
+        }
+    }
+
+    pub fn format(&self) -> RecordFormat {
+        self.format
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn push_frame(&mut self, pixels: &PixelBuffer) -> Result<(), RecordError> {
+        self.persistence.update(pixels);
+        let scale = self.scale;
+        let (width, height) = *self
+            .size
+            .get_or_insert((pixels.columns() * scale, pixels.rows() * scale));
+        let levels = self.sample(width, height);
+
+        let sink = match self.sink.take() {
+            Some(Sink::Pending(writer)) => self.start(writer, width, height)?,
+            Some(sink) => sink,
+            None => return Ok(()),
+        };
+        let palette = self.palette;
+        let sink = self.sink.get_or_insert(sink);
+        match sink {
+            Sink::Gif(encoder) => {
+                let indices: Vec<u8> = levels
+                    .iter()
+                    .map(|level| (level * (GIF_LEVELS - 1) as f32).round() as u8)
+                    .collect();
+                let mut frame =
+                    gif::Frame::from_indexed_pixels(width as u16, height as u16, &indices, None);
+                //GIF delays are in 1/100s, alternate them so the average is 60 fps.
+                frame.delay = ((self.frames + 1) * 100 / 60 - self.frames * 100 / 60) as u16;
+                encoder.write_frame(&frame).context(GifError)?;
+            }
+            Sink::Stream(writer) => {
+                let rgb: Vec<(u8, u8, u8)> =
+                    levels.iter().map(|level| palette.blend(*level)).collect();
+                match self.format {
+                    RecordFormat::Y4m => write_y4m_frame(writer, &rgb),
+                    _ => write_raw_frame(writer, &rgb),
+                }
+                .context(WriteError)?;
+            }
+            Sink::Pending(_) => unreachable!(),
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    //Flush everything and close the file; GIFs get their trailer here.
+    pub fn finish(mut self) -> Result<(), RecordError> {
+        match self.sink.take() {
+            Some(Sink::Gif(encoder)) => encoder
+                .into_inner()
+                .and_then(|mut writer| writer.flush())
+                .context(WriteError),
+            Some(Sink::Stream(mut writer)) | Some(Sink::Pending(mut writer)) => {
+                writer.flush().context(WriteError)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn start(
+        &self,
+        mut writer: Box<dyn Write>,
+        width: usize,
+        height: usize,
+    ) -> Result<Sink, RecordError> {
+        match self.format {
+            RecordFormat::Gif => {
+                if width > u16::MAX as usize || height > u16::MAX as usize {
+                    return GifSizeError { width, height }.fail();
+                }
+                let colors: Vec<u8> = (0..GIF_LEVELS)
+                    .flat_map(|level| {
+                        let (r, g, b) = self.palette.blend(level as f32 / (GIF_LEVELS - 1) as f32);
+                        vec![r, g, b]
+                    })
+                    .collect();
+                let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &colors)
+                    .context(GifError)?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .context(GifError)?;
+                Ok(Sink::Gif(encoder))
+            }
+            RecordFormat::Y4m => {
+                writeln!(
+                    writer,
+                    "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444",
+                    width, height
+                )
+                .context(WriteError)?;
+                Ok(Sink::Stream(writer))
+            }
+            RecordFormat::Raw => Ok(Sink::Stream(writer)),
+        }
+    }
+
+    //Intensity of every output pixel. The size is fixed by the first frame,
+    //so a pixel buffer of another size is resampled to fit.
+    fn sample(&self, width: usize, height: usize) -> Vec<f32> {
+        let (columns, rows) = (self.persistence.columns(), self.persistence.rows());
+        let mut levels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                levels.push(
+                    self.persistence
+                        .intensity(x * columns / width, y * rows / height),
+                );
+            }
+        }
+        levels
+    }
+}
+
+fn write_raw_frame(writer: &mut Box<dyn Write>, rgb: &[(u8, u8, u8)]) -> io::Result<()> {
+    let bytes: Vec<u8> = rgb.iter().flat_map(|&(r, g, b)| vec![r, g, b]).collect();
+    writer.write_all(&bytes)
+}
+
+//BT.601 studio swing, full resolution chroma planes.
+fn write_y4m_frame(writer: &mut Box<dyn Write>, rgb: &[(u8, u8, u8)]) -> io::Result<()> {
+    let convert = |f: &dyn Fn(f32, f32, f32) -> f32| -> Vec<u8> {
+        rgb.iter()
+            .map(|&(r, g, b)| f(r as f32, g as f32, b as f32).round() as u8)
+            .collect()
+    };
+    let y = convert(&|r, g, b| 16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0);
+    let u = convert(&|r, g, b| 128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0);
+    let v = convert(&|r, g, b| 128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0);
+    writer.write_all(b"FRAME\n")?;
+    writer.write_all(&y)?;
+    writer.write_all(&u)?;
+    writer.write_all(&v)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::framebuffer::PixelBuffer;
+    use crate::palette::Palette;
+    use crate::record::{RecordFormat, Recorder};
+    use std::cell::RefCell;
+    use std::io::{self, Cursor, Write};
+    use std::path::Path;
+    use std::rc::Rc;
+
+    //A Cursor the test can still read after the recorder is finished with it.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    //Two frames, the top left pixel lit in the second, white on black.
+    fn record(format: RecordFormat) -> Vec<u8> {
+        let output = Shared::default();
+        let palette: Palette = "ffffff,000000".parse().unwrap();
+        let mut recorder = Recorder::new(Box::new(output.clone()), format, palette, 0.0, 1);
+        let mut pixels = PixelBuffer::new(64, 32);
+        recorder.push_frame(&pixels).unwrap();
+        pixels.set(0, 0, true);
+        recorder.push_frame(&pixels).unwrap();
+        assert_eq!(recorder.frames(), 2);
+        recorder.finish().unwrap();
+        let bytes = output.0.borrow().get_ref().clone();
+        bytes
+    }
+
+    #[test]
+    fn y4m_test() {
+        let bytes = record(RecordFormat::Y4m);
+        let header = b"YUV4MPEG2 W64 H32 F60:1 Ip A1:1 C444\n";
+        assert_eq!(&bytes[..header.len()], &header[..]);
+        //FRAME, then full size Y, U and V planes.
+        let frame_size = 6 + 3 * 64 * 32;
+        let frames = &bytes[header.len()..];
+        assert_eq!(frames.len(), 2 * frame_size);
+        for frame in frames.chunks(frame_size) {
+            assert_eq!(&frame[..6], b"FRAME\n");
+        }
+        //Studio swing: 16 for black, 235 for white.
+        assert_eq!(frames[6], 16);
+        assert_eq!(frames[frame_size + 6], 235);
+        assert_eq!(frames[frame_size + 7], 16);
+    }
+
+    #[test]
+    fn gif_test() {
+        let bytes = record(RecordFormat::Gif);
+        let mut decoder = gif::DecodeOptions::new().read_info(&bytes[..]).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (64, 32));
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height), (64, 32));
+            frames += 1;
+        }
+        assert_eq!(frames, 2);
+    }
+
+    #[test]
+    fn format_test() {
+        assert_eq!(
+            RecordFormat::from_path(Path::new("a.gif")),
+            RecordFormat::Gif
+        );
+        assert_eq!(
+            RecordFormat::from_path(Path::new("a.y4m")),
+            RecordFormat::Y4m
+        );
+        assert_eq!(
+            RecordFormat::from_path(Path::new("a.rgb")),
+            RecordFormat::Raw
+        );
+        assert_eq!(RecordFormat::from_path(Path::new("-")), RecordFormat::Y4m);
+    }
+}
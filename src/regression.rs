@@ -0,0 +1,283 @@
+use crate::emulator::Emulator;
+use crate::framebuffer::PixelBuffer;
+use crate::keypad::Keypad;
+use crate::rom::Rom;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+//Set to rewrite golden files from the current output instead of comparing.
+pub const UPDATE_ENV: &str = "UPDATE_GOLDEN";
+
+//Key presses and releases at given frames, one per line:
+//  <frame> press <key>
+//  <frame> release <key>
+//Keys are hex digits, lines starting with # are comments.
+#[derive(Debug, Default, Clone)]
+pub struct InputScript {
+    events: Vec<(u32, u8, bool)>,
+}
+
+impl InputScript {
+    pub fn new() -> InputScript {
+        InputScript::default()
+    }
+
+    pub fn press(mut self, frame: u32, key: u8) -> InputScript {
+        self.events.push((frame, key, true));
+        self
+    }
+
+    pub fn release(mut self, frame: u32, key: u8) -> InputScript {
+        self.events.push((frame, key, false));
+        self
+    }
+
+    //Apply every event scheduled for this frame.
+    pub fn apply(&self, frame: u32, keypad: &mut Keypad) {
+        for &(_, key, pressed) in self.events.iter().filter(|e| e.0 == frame) {
+            keypad.set(key, pressed);
+        }
+    }
+}
+
+impl FromStr for InputScript {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut script = InputScript::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || format!("Bad input script line {}: {}", number + 1, line);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() != 3 {
+                return Err(error());
+            }
+            let frame = parts[0].parse().map_err(|_| error())?;
+            let key = u8::from_str_radix(parts[2], 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(error)?;
+            script = match parts[1] {
+                "press" => script.press(frame, key),
+                "release" => script.release(frame, key),
+                _ => return Err(error()),
+            };
+        }
+        Ok(script)
+    }
+}
+
+//Run a ROM headless for some frames, feeding the scripted input before each frame.
+pub fn run(rom: &Rom, frames: u32, script: &InputScript) -> Result<Snapshot, String> {
+    let mut emulator = Emulator::new();
+    emulator.load(rom).map_err(|e| e.to_string())?;
+    for frame in 0..frames {
+        script.apply(frame, emulator.keypad_mut());
+        emulator.run_frame().map_err(|e| e.to_string())?;
+    }
+    Ok(Snapshot::from_pixels(emulator.pixels()))
+}
+
+//Frozen copy of the screen that can be stored as text and compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    columns: usize,
+    rows: usize,
+    pixels: Vec<bool>,
+}
+
+impl Snapshot {
+    pub fn from_pixels(buffer: &PixelBuffer) -> Snapshot {
+        let mut pixels = Vec::with_capacity(buffer.columns() * buffer.rows());
+        for row in 0..buffer.rows() {
+            for column in 0..buffer.columns() {
+                pixels.push(buffer.get(column, row));
+            }
+        }
+        Snapshot {
+            columns: buffer.columns(),
+            rows: buffer.rows(),
+            pixels,
+        }
+    }
+
+    //FNV-1a over the size and pixels.
+    pub fn hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let size = [self.columns as u8, self.rows as u8];
+        let pixels = self.pixels.iter().map(|lit| *lit as u8);
+        for byte in size.iter().cloned().chain(pixels) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+
+    fn get(&self, column: usize, row: usize) -> bool {
+        self.pixels[row * self.columns + column]
+    }
+
+    //None when equal, otherwise a picture of the expected screen where
+    //+ is a pixel that is lit but should not be and x one that is missing.
+    pub fn diff(&self, actual: &Snapshot) -> Option<String> {
+        if self == actual {
+            return None;
+        }
+        if self.columns != actual.columns || self.rows != actual.rows {
+            return Some(format!(
+                "Expected a {}x{} screen, got {}x{}",
+                self.columns, self.rows, actual.columns, actual.rows
+            ));
+        }
+        let mut mismatched = 0;
+        let mut picture = String::new();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                picture.push(match (self.get(column, row), actual.get(column, row)) {
+                    (true, true) => '#',
+                    (false, false) => '.',
+                    (false, true) => '+',
+                    (true, false) => 'x',
+                });
+                if self.get(column, row) != actual.get(column, row) {
+                    mismatched += 1;
+                }
+            }
+            picture.push('\n');
+        }
+        Some(format!(
+            "{} pixels differ (+ unexpected, x missing):\n{}",
+            mismatched, picture
+        ))
+    }
+}
+
+//Text format: "<columns>x<rows> <hash>" followed by one line per row of # and .
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}x{} {:016x}", self.columns, self.rows, self.hash())?;
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                write!(f, "{}", if self.get(column, row) { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        let header = lines.next().ok_or("Empty snapshot")?;
+        let size = header.split_whitespace().next().unwrap_or("");
+        let mut size = size.split('x').map(|n| n.parse::<usize>());
+        let (columns, rows) = match (size.next(), size.next()) {
+            (Some(Ok(columns)), Some(Ok(rows))) => (columns, rows),
+            _ => return Err(format!("Bad snapshot header: {}", header)),
+        };
+        let mut pixels = Vec::with_capacity(columns * rows);
+        for line in lines.take(rows) {
+            if line.chars().count() != columns {
+                return Err(format!(
+                    "Snapshot row should be {} pixels: {}",
+                    columns, line
+                ));
+            }
+            pixels.extend(line.chars().map(|c| c == '#'));
+        }
+        if pixels.len() != columns * rows {
+            return Err(format!("Snapshot should have {} rows", rows));
+        }
+        Ok(Snapshot {
+            columns,
+            rows,
+            pixels,
+        })
+    }
+}
+
+//Compare against a golden file, or write it when UPDATE_GOLDEN is set. A missing
+//golden fails like a mismatch, new ones have to be written on purpose.
+pub fn check_golden<T: AsRef<Path>>(golden: T, actual: &Snapshot) -> Result<(), String> {
+    let golden = golden.as_ref();
+    if std::env::var_os(UPDATE_ENV).is_some() {
+        return fs::write(golden, actual.to_string())
+            .map_err(|e| format!("Could not write {}: {}", golden.display(), e));
+    }
+    if !golden.exists() {
+        return Err(format!(
+            "Missing golden {}, run with {}=1 to create it",
+            golden.display(),
+            UPDATE_ENV
+        ));
+    }
+    let expected: Snapshot = fs::read_to_string(golden)
+        .map_err(|e| format!("Could not read {}: {}", golden.display(), e))?
+        .parse()?;
+    match expected.diff(actual) {
+        None => Ok(()),
+        Some(diff) => Err(format!("{} does not match\n{}", golden.display(), diff)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::framebuffer::{PixelBuffer, Sprite};
+    use crate::keypad::Keypad;
+    use crate::regression::{check_golden, InputScript, Snapshot, UPDATE_ENV};
+
+    #[test]
+    fn snapshot_round_trip_test() {
+        let mut pixels = PixelBuffer::new(8, 2);
+        pixels.add_sprite(0, 0, Sprite::new(&[0xA0, 0x01]));
+        let snapshot = Snapshot::from_pixels(&pixels);
+        let text = snapshot.to_string();
+        assert!(text.ends_with("#.#.....\n.......#\n"));
+        assert_eq!(text.parse::<Snapshot>().unwrap(), snapshot);
+    }
+
+    #[test]
+    fn diff_test() {
+        let mut pixels = PixelBuffer::new(4, 1);
+        let expected = Snapshot::from_pixels(&pixels);
+        assert_eq!(expected.diff(&expected), None);
+        pixels.add_sprite(1, 0, Sprite::new(&[0x80]));
+        let actual = Snapshot::from_pixels(&pixels);
+        assert_ne!(expected.hash(), actual.hash());
+        let diff = expected.diff(&actual).unwrap();
+        assert!(diff.starts_with("1 pixels differ"));
+        assert!(diff.ends_with(".+..\n"));
+    }
+
+    #[test]
+    fn missing_golden_test() {
+        if std::env::var_os(UPDATE_ENV).is_some() {
+            return;
+        }
+        let golden = std::env::temp_dir().join("chip8forever-no-such-golden.txt");
+        let snapshot = Snapshot::from_pixels(&PixelBuffer::new(4, 1));
+        let error = check_golden(&golden, &snapshot).unwrap_err();
+        assert!(error.starts_with("Missing golden"));
+        assert!(!golden.exists());
+    }
+
+    #[test]
+    fn input_script_test() {
+        let script: InputScript = "# start\n2 press a\n5 release A\n".parse().unwrap();
+        let mut keypad = Keypad::new();
+        script.apply(2, &mut keypad);
+        assert!(keypad.is_pressed(0xA));
+        script.apply(5, &mut keypad);
+        assert!(!keypad.is_pressed(0xA));
+        assert!("1 press 10".parse::<InputScript>().is_err());
+        assert!("x press 1".parse::<InputScript>().is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use crate::cartridge::{Cartridge, CartridgeError};
+use crate::disasm::Symbols;
+use crate::mem::MAX_MEM_SIZE;
+use crate::memmap::MemoryMap;
+use crate::octo::{self, AssemblyError};
+use crate::romdb::{Database, Profile};
+use log::debug;
+use snafu::{ensure, ResultExt, Snafu};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+
+//Programs are usually loaded here, everything below belongs to the interpreter.
+pub const PROGRAM_START: u16 = 0x200;
+//Largest program that fits between PROGRAM_START and the end of the largest memory.
+//Rom::fits checks a particular memory map.
+pub const MAX_SIZE: usize = MAX_MEM_SIZE - PROGRAM_START as usize;
+//Extensions of CHIP-8, SUPER-CHIP and XO-CHIP programs, looked for inside archives.
+pub const EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+
+#[derive(Debug, Snafu)]
+pub enum RomError {
+    #[snafu(display("Could not load ROM from file {}: {}", filename.display(), source))]
+    FileError {
+        filename: PathBuf,
+        source: io::Error,
+    },
+    #[snafu(display("Could not read ROM: {}", source))]
+    ReadError { source: io::Error },
+    #[snafu(display("ROM is empty"))]
+    Empty,
+    #[snafu(display("ROM is {} bytes, at most {} fit in memory", size, max))]
+    TooLarge { size: usize, max: usize },
+    #[snafu(display("Could not read archive: {}", source))]
+    ArchiveError { source: zip::result::ZipError },
+    #[snafu(display("No .ch8, .sc8 or .xo8 file in the archive"))]
+    NoRomInArchive,
+    #[snafu(display("The archive has several ROMs, pick one of {}", names.join(", ")))]
+    SeveralRoms { names: Vec<String> },
+    #[snafu(display("{}", source))]
+    BadCartridge { source: CartridgeError },
+    #[snafu(display("Could not assemble: {}", source))]
+    BadSource { source: AssemblyError },
+}
+
+#[derive(Debug)]
+pub struct Rom {
+    content: Vec<u8>,
+    profile: Option<Profile>,
+    symbols: Symbols,
+}
+
+impl Rom {
+    //Checks that the program fits in memory, then looks it up in the
+    //embedded database or guesses its platform.
+    pub fn new(content: Vec<u8>) -> Result<Rom, RomError> {
+        ensure!(!content.is_empty(), Empty);
+        ensure!(
+            content.len() <= MAX_SIZE,
+            TooLarge {
+                size: content.len(),
+                max: MAX_SIZE
+            }
+        );
+        let mut rom = Rom {
+            content,
+            profile: None,
+            symbols: Symbols::default(),
+        };
+        rom.profile = Database::embedded().identify(&rom.sha1(), &rom.content);
+        Ok(rom)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Rom, RomError> {
+        Rom::new(bytes.to_vec())
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Rom, RomError> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer).context(ReadError)?;
+        Rom::new(buffer)
+    }
+
+    //The single ROM in a zip archive, or the entry with the given name.
+    pub fn from_archive<R: Read + Seek>(reader: R, entry: Option<&str>) -> Result<Rom, RomError> {
+        let mut archive = zip::ZipArchive::new(reader).context(ArchiveError)?;
+        let name = match entry {
+            Some(name) => name.to_string(),
+            None => {
+                let mut names: Vec<String> = archive
+                    .file_names()
+                    .filter(|name| has_rom_extension(Path::new(name)))
+                    .map(String::from)
+                    .collect();
+                names.sort();
+                ensure!(!names.is_empty(), NoRomInArchive);
+                ensure!(names.len() == 1, SeveralRoms { names });
+                names.remove(0)
+            }
+        };
+        let file = archive.by_name(&name).context(ArchiveError)?;
+        debug!("Reading {} from the archive", name);
+        Rom::from_reader(file)
+    }
+
+    //Assembles Octo source, keeping its labels for traces and disassembly.
+    pub fn from_source(source: &str) -> Result<Rom, RomError> {
+        let program = octo::assemble(source).context(BadSource)?;
+        let mut rom = Rom::new(program.bytes)?;
+        rom.symbols = program.symbols;
+        Ok(rom)
+    }
+
+    //Octo cartridge GIF. Its options become the ROM's profile.
+    pub fn from_cartridge<R: Read>(reader: R) -> Result<Rom, RomError> {
+        let cartridge = Cartridge::from_reader(reader).context(BadCartridge)?;
+        let mut rom = Rom::from_source(&cartridge.program)?;
+        rom.profile = Some(cartridge.profile());
+        Ok(rom)
+    }
+
+    //A path of "-" reads the ROM from standard input. Zip archives, Octo source
+    //and Octo cartridges are recognised by their extension.
+    pub fn from_file<T: AsRef<Path>>(path: T) -> Result<Self, RomError> {
+        Rom::from_file_entry(path, None)
+    }
+
+    //Like from_file, with the name of the ROM to use when an archive has several.
+    pub fn from_file_entry<T: AsRef<Path>>(path: T, entry: Option<&str>) -> Result<Self, RomError> {
+        let filename = path.as_ref();
+        if filename == Path::new("-") {
+            return Rom::from_reader(io::stdin().lock());
+        }
+        let mut file = File::open(filename).context(FileError { filename })?;
+        match extension(filename).as_deref() {
+            Some("zip") => return Rom::from_archive(BufReader::new(file), entry),
+            Some("gif") => return Rom::from_cartridge(BufReader::new(file)),
+            Some("8o") => {
+                let mut source = String::new();
+                file.read_to_string(&mut source)
+                    .context(FileError { filename })?;
+                return Rom::from_source(&source);
+            }
+            _ => {}
+        }
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)
+            .context(FileError { filename })?;
+        debug!("Read {} bytes from {}", buffer.len(), filename.display());
+        Rom::new(buffer)
+    }
+
+    //Checks the program fits between the load address and the interpreter's
+    //memory above it, or the end of memory.
+    pub fn fits(&self, map: &MemoryMap) -> Result<(), RomError> {
+        let max = map.max_program_size();
+        let size = self.content.len();
+        ensure!(size <= max, TooLarge { size, max });
+        Ok(())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.content
+    }
+
+    //Lowercase hex, the key used by the config file and ROM databases.
+    pub fn sha1(&self) -> String {
+        sha1_smol::Sha1::from(&self.content).digest().to_string()
+    }
+
+    //CRC-32 as used by zip files and most ROM sets.
+    pub fn crc32(&self) -> u32 {
+        crc32fast::hash(&self.content)
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    //Labels when the ROM was assembled from source, empty otherwise.
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+fn has_rom_extension(path: &Path) -> bool {
+    extension(path).is_some_and(|extension| EXTENSIONS.contains(&extension.as_str()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memmap::MemoryMap;
+    use crate::rom::{Rom, RomError, MAX_SIZE};
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn size_test() {
+        assert!(matches!(Rom::new(Vec::new()), Err(RomError::Empty)));
+        assert!(Rom::new(vec![0; MAX_SIZE]).is_ok());
+        assert!(matches!(
+            Rom::new(vec![0; MAX_SIZE + 1]),
+            Err(RomError::TooLarge {
+                size: 65025,
+                max: 65024
+            })
+        ));
+        let rom = Rom::new(vec![0; 0x1000]).unwrap();
+        assert!(rom.fits(&MemoryMap::xochip()).is_ok());
+        assert!(matches!(
+            rom.fits(&MemoryMap::vip()),
+            Err(RomError::TooLarge {
+                size: 4096,
+                max: 3232
+            })
+        ));
+        //Up to the VIP interpreter's variables and display buffer at 0xEA0.
+        assert!(Rom::new(vec![0; 0xCA0])
+            .unwrap()
+            .fits(&MemoryMap::vip())
+            .is_ok());
+    }
+
+    #[test]
+    fn hash_test() {
+        let rom = Rom::from_bytes(b"abc").unwrap();
+        assert_eq!(rom.sha1(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(rom.crc32(), 0x3524_41C2);
+        assert_eq!(rom.as_bytes(), b"abc");
+    }
+
+    #[test]
+    fn from_reader_test() {
+        let rom = Rom::from_reader(&[0x00, 0xE0][..]).unwrap();
+        assert_eq!(rom.as_bytes(), &[0x00, 0xE0]);
+        assert!(matches!(Rom::from_reader(&[][..]), Err(RomError::Empty)));
+    }
+
+    fn archive(files: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(content).unwrap();
+        }
+        Cursor::new(writer.finish().unwrap().into_inner())
+    }
+
+    #[test]
+    fn source_test() {
+        let rom = Rom::from_source(": main\n  loop again").unwrap();
+        assert_eq!(rom.as_bytes(), &[0x12, 0x02, 0x12, 0x02]);
+        assert_eq!(rom.symbols().name(0x202), Some("main"));
+        assert!(Rom::from_bytes(&[0x12, 0x00]).unwrap().symbols().is_empty());
+        assert!(matches!(
+            Rom::from_source("clear"),
+            Err(RomError::BadSource { .. })
+        ));
+    }
+
+    #[test]
+    fn archive_test() {
+        let single = archive(&[("readme.txt", b"Pong"), ("games/PONG.CH8", &[0x00, 0xE0])]);
+        let rom = Rom::from_archive(single, None).unwrap();
+        assert_eq!(rom.as_bytes(), &[0x00, 0xE0]);
+
+        let files: &[(&str, &[u8])] = &[("b.sc8", &[0x00, 0xFF]), ("a.ch8", &[0x00, 0xE0])];
+        match Rom::from_archive(archive(files), None) {
+            Err(RomError::SeveralRoms { names }) => assert_eq!(names, ["a.ch8", "b.sc8"]),
+            other => panic!("expected SeveralRoms, got {:?}", other),
+        }
+        let rom = Rom::from_archive(archive(files), Some("b.sc8")).unwrap();
+        assert_eq!(rom.as_bytes(), &[0x00, 0xFF]);
+
+        assert!(matches!(
+            Rom::from_archive(archive(&[("readme.txt", b"Pong")]), None),
+            Err(RomError::NoRomInArchive)
+        ));
+        assert!(matches!(
+            Rom::from_archive(Cursor::new(vec![0x00, 0xE0]), None),
+            Err(RomError::ArchiveError { .. })
+        ));
+    }
+}
@@ -0,0 +1,463 @@
+use crate::config::Layer;
+use crate::memmap::MemoryMap;
+use crate::palette::Palette;
+use crate::quirks::{MemoryIncrement, Quirks};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::sync::OnceLock;
+
+//A small database in the format of https://github.com/chip-8/chip-8-database,
+//covering the bundled ROMs. The upstream files can be dropped in as they are.
+const PROGRAMS: &str = include_str!("../res/romdb/programs.json");
+const HASHES: &str = include_str!("../res/romdb/sha1-hashes.json");
+const PLATFORMS: &str = include_str!("../res/romdb/platforms.json");
+
+#[derive(Debug, Deserialize)]
+struct Program {
+    title: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    #[serde(default)]
+    platforms: Vec<String>,
+    tickrate: Option<u32>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+    colors: Option<Colors>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Colors {
+    //Background first, then the foreground; extra XO-CHIP plane colours are ignored.
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Platform {
+    id: String,
+    default_tickrate: Option<u32>,
+    quirks: PlatformQuirks,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlatformQuirks {
+    shift: bool,
+    memory_increment_by_x: bool,
+    memory_leave_i_unchanged: bool,
+    jump: bool,
+    logic: bool,
+}
+
+impl PlatformQuirks {
+    fn quirks(&self) -> Quirks {
+        Quirks {
+            shift_vy: !self.shift,
+            memory_increment: match (self.memory_leave_i_unchanged, self.memory_increment_by_x) {
+                (true, _) => MemoryIncrement::Unchanged,
+                (false, true) => MemoryIncrement::X,
+                (false, false) => MemoryIncrement::XPlusOne,
+            },
+            vf_reset: self.logic,
+            jump_vx: self.jump,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Database,
+    //Guessed from the opcodes in the ROM.
+    Heuristic,
+    //Options stored in an Octo cartridge.
+    Cartridge,
+}
+
+//What is known about a ROM and the settings it needs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    //Platform id from the database, like originalChip8 or xochip.
+    pub platform: String,
+    pub quirks: Option<Quirks>,
+    pub memory_map: Option<MemoryMap>,
+    pub tickrate: Option<u32>,
+    //Keyboard key names for keypad keys.
+    pub keys: Vec<(u8, String)>,
+    pub palette: Option<Palette>,
+    pub source: Source,
+    //False when the program uses opcodes the emulator does not have yet.
+    pub supported: bool,
+}
+
+impl Profile {
+    //Settings for the config, applied under the ROM's own config section.
+    //Nothing for unsupported programs, which would not run with them either.
+    pub fn layer(&self) -> Layer {
+        if !self.supported {
+            return Layer::default();
+        }
+        Layer {
+            palette: self.palette,
+            quirks: self.quirks,
+            memory_map: self.memory_map,
+            ipf: self.tickrate,
+            keys: self.keys.clone(),
+            ..Layer::default()
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match (&self.title, self.source) {
+            (Some(title), _) if self.authors.is_empty() => write!(f, "{}", title),
+            (Some(title), _) => write!(f, "{} by {}", title, self.authors.join(", ")),
+            (None, Source::Heuristic) => write!(f, "Looks like a {} program", self.platform),
+            (None, Source::Database) => write!(f, "Known {} program", self.platform),
+            (None, Source::Cartridge) => write!(f, "Octo cartridge"),
+        }?;
+        match self.supported {
+            true => Ok(()),
+            false => write!(f, " (unsupported platform {})", self.platform),
+        }
+    }
+}
+
+pub struct Database {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>,
+    platforms: Vec<Platform>,
+}
+
+impl Database {
+    //Contents of programs.json, sha1-hashes.json and platforms.json.
+    pub fn parse(
+        programs: &str,
+        hashes: &str,
+        platforms: &str,
+    ) -> Result<Database, serde_json::Error> {
+        Ok(Database {
+            programs: serde_json::from_str(programs)?,
+            hashes: serde_json::from_str(hashes)?,
+            platforms: serde_json::from_str(platforms)?,
+        })
+    }
+
+    pub fn embedded() -> &'static Database {
+        static DATABASE: OnceLock<Database> = OnceLock::new();
+        DATABASE.get_or_init(|| {
+            Database::parse(PROGRAMS, HASHES, PLATFORMS).expect("Embedded ROM database is valid")
+        })
+    }
+
+    fn platform(&self, id: &str) -> Option<&Platform> {
+        self.platforms.iter().find(|platform| platform.id == id)
+    }
+
+    fn lookup(&self, sha1: &str) -> Option<Profile> {
+        let sha1 = sha1.to_lowercase();
+        let program = self.programs.get(*self.hashes.get(&sha1)?)?;
+        let rom = program.roms.get(&sha1)?;
+        //The first platform listed is the one the ROM was made for.
+        let platform_id = rom.platforms.first().cloned().unwrap_or_default();
+        let platform = self.platform(&platform_id);
+        let palette = rom.colors.as_ref().and_then(|colors| {
+            match (colors.pixels.get(1), colors.pixels.first()) {
+                (Some(fg), Some(bg)) => format!("{},{}", fg, bg).parse().ok(),
+                _ => None,
+            }
+        });
+        Some(Profile {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            quirks: platform.map(|platform| platform.quirks.quirks()),
+            memory_map: memory_map(&platform_id),
+            tickrate: rom
+                .tickrate
+                .or_else(|| platform.and_then(|platform| platform.default_tickrate)),
+            keys: rom
+                .keys
+                .iter()
+                .filter_map(|(button, &key)| Some((key, button_key(button)?.to_string())))
+                .filter(|(key, _)| *key < 16)
+                .collect(),
+            palette,
+            platform: platform_id,
+            source: Source::Database,
+            supported: true,
+        })
+    }
+
+    //Known ROMs from the database, otherwise a guess from the opcodes used.
+    //Plain CHIP-8 programs get None so the defaults stay in place.
+    pub fn identify(&self, sha1: &str, program: &[u8]) -> Option<Profile> {
+        let scan = scan(program);
+        if let Some(profile) = self.lookup(sha1) {
+            return Some(Profile {
+                supported: !scan.missing,
+                ..profile
+            });
+        }
+        let platform_id = scan.platform()?;
+        let platform = self.platform(platform_id);
+        Some(Profile {
+            title: None,
+            authors: Vec::new(),
+            platform: platform_id.to_string(),
+            quirks: platform.map(|platform| platform.quirks.quirks()),
+            memory_map: memory_map(platform_id),
+            tickrate: platform.and_then(|platform| platform.default_tickrate),
+            keys: Vec::new(),
+            palette: None,
+            source: Source::Heuristic,
+            supported: !scan.missing,
+        })
+    }
+}
+
+//Memory layout for the database's platform ids. Others keep the default.
+fn memory_map(platform_id: &str) -> Option<MemoryMap> {
+    match platform_id {
+        "originalChip8" | "hybridVIP" => Some(MemoryMap::vip()),
+        "chip48" | "superchip1" | "superchip" => Some(MemoryMap::schip()),
+        "xochip" => Some(MemoryMap::xochip()),
+        _ => None,
+    }
+}
+
+//Keyboard keys for the database's button names. Second player buttons keep the default layout.
+fn button_key(button: &str) -> Option<&'static str> {
+    match button {
+        "up" => Some("Up"),
+        "down" => Some("Down"),
+        "left" => Some("Left"),
+        "right" => Some("Right"),
+        "a" => Some("Space"),
+        "b" => Some("Left Shift"),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Chip8,
+    Schip,
+    XoChip,
+}
+
+//Oldest platform that has this opcode.
+fn opcode_level(nibbles: (u16, u16, u16, u16)) -> Level {
+    match nibbles {
+        //Scroll up, long I, planes, audio, pitch, register ranges.
+        (0x0, 0x0, 0xD, _)
+        | (0xF, 0x0, 0x0, 0x0)
+        | (0xF, _, 0x0, 0x1)
+        | (0xF, 0x0, 0x0, 0x2)
+        | (0xF, _, 0x3, 0xA)
+        | (0x5, _, _, 0x2)
+        | (0x5, _, _, 0x3) => Level::XoChip,
+        //Scrolling, exit, resolution switch, big font, flag registers.
+        (0x0, 0x0, 0xC, _)
+        | (0x0, 0x0, 0xF, 0xB..=0xF)
+        | (0xF, _, 0x3, 0x0)
+        | (0xF, _, 0x7, 0x5)
+        | (0xF, _, 0x8, 0x5) => Level::Schip,
+        _ => Level::Chip8,
+    }
+}
+
+//Opcodes of later platforms the CPU does not run yet.
+fn missing(nibbles: (u16, u16, u16, u16)) -> bool {
+    matches!(
+        nibbles,
+        (0x0, 0x0, 0xC, _)
+            | (0x0, 0x0, 0xD, _)
+            | (0x0, 0x0, 0xF, 0xB..=0xF)
+            | (0xF, 0x0, 0x0, 0x0)
+            | (0xF, _, 0x0, 0x1)
+            | (0xF, _, 0x7, 0x5)
+            | (0xF, _, 0x8, 0x5)
+            | (0x5, _, _, 0x2)
+            | (0x5, _, _, 0x3)
+    )
+}
+
+struct Scan {
+    level: Level,
+    //Some opcode the CPU does not run yet was found.
+    missing: bool,
+}
+
+impl Scan {
+    fn platform(&self) -> Option<&'static str> {
+        match self.level {
+            Level::Chip8 => None,
+            Level::Schip => Some("superchip"),
+            Level::XoChip => Some("xochip"),
+        }
+    }
+}
+
+//Follows jumps, calls and skips from the entry point so sprite data is not mistaken
+//for code, and reports opcodes only later platforms have. BNNN and self-modifying
+//code can hide instructions, so this is only a guess.
+fn scan(program: &[u8]) -> Scan {
+    const START: usize = 0x200;
+    let mut visited = vec![false; program.len()];
+    let mut pending = vec![START];
+    let mut level = Level::Chip8;
+    let mut missing_opcode = false;
+    while let Some(address) = pending.pop() {
+        let offset = match address.checked_sub(START) {
+            Some(offset) if offset + 1 < program.len() && !visited[offset] => offset,
+            _ => continue,
+        };
+        visited[offset] = true;
+        let opcode = u16::from_be_bytes([program[offset], program[offset + 1]]);
+        let nibbles = (
+            opcode >> 12,
+            opcode >> 8 & 0xF,
+            opcode >> 4 & 0xF,
+            opcode & 0xF,
+        );
+        level = level.max(opcode_level(nibbles));
+        missing_opcode |= missing(nibbles);
+        //F000 is followed by a 16-bit address.
+        let next = if opcode == 0xF000 {
+            address + 4
+        } else {
+            address + 2
+        };
+        let target = (opcode & 0xFFF) as usize;
+        match nibbles {
+            (0x0, 0x0, 0xE, 0xE) | (0x0, 0x0, 0xF, 0xD) | (0xB, _, _, _) => {}
+            (0x1, _, _, _) => pending.push(target),
+            (0x2, _, _, _) => pending.extend_from_slice(&[target, next]),
+            //Skips, which may jump over a four byte F000.
+            (0x3, _, _, _)
+            | (0x4, _, _, _)
+            | (0x5, _, _, 0x0)
+            | (0x9, _, _, 0x0)
+            | (0xE, _, 0x9, 0xE)
+            | (0xE, _, 0xA, 0x1) => {
+                let skipped = program.get(next - START..next - START + 2);
+                let over = if skipped == Some(&[0xF0, 0x00]) { 4 } else { 2 };
+                pending.extend_from_slice(&[next, next + over]);
+            }
+            _ => pending.push(next),
+        }
+    }
+    Scan {
+        level,
+        missing: missing_opcode,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Layer;
+    use crate::quirks::Quirks;
+    use crate::romdb::{scan, Database, Source};
+
+    #[test]
+    fn lookup_test() {
+        let profile = Database::embedded()
+            .lookup("607C4F7F4E4DCE9F99D96B3182BFE7E88BB090EE")
+            .unwrap();
+        assert_eq!(profile.to_string(), "Pong (1 player) by Paul Vervalin");
+        assert_eq!(profile.platform, "originalChip8");
+        assert_eq!(profile.quirks, Some(Quirks::vip()));
+        assert_eq!(profile.tickrate, Some(15));
+        assert!(profile.keys.contains(&(1, "Up".to_string())));
+        assert_eq!(profile.source, Source::Database);
+        assert!(Database::embedded().lookup("0000").is_none());
+    }
+
+    #[test]
+    fn platform_quirks_test() {
+        let db = Database::embedded();
+        let quirks = |id| db.platform(id).unwrap().quirks.quirks();
+        assert_eq!(quirks("chip48"), Quirks::chip48());
+        assert_eq!(quirks("superchip"), Quirks::schip());
+        assert_eq!(quirks("xochip"), Quirks::xochip());
+    }
+
+    #[test]
+    fn colors_test() {
+        let programs = r##"[{"title": "Test", "roms": {"abcd": {
+            "platforms": ["xochip"], "tickrate": 100,
+            "colors": {"pixels": ["#000000", "#ff8000"]}}}}]"##;
+        let db = Database::parse(programs, r#"{"abcd": 0}"#, "[]").unwrap();
+        let profile = db.lookup("abcd").unwrap();
+        assert_eq!(profile.palette.unwrap().foreground, (0xFF, 0x80, 0x00));
+        assert_eq!(profile.tickrate, Some(100));
+        assert_eq!(profile.quirks, None);
+    }
+
+    #[test]
+    fn detect_platform_test() {
+        assert_eq!(scan(&[0x00, 0xE0, 0x12, 0x00]).platform(), None);
+        assert_eq!(
+            scan(&[0x00, 0xFF, 0x12, 0x00]).platform(),
+            Some("superchip")
+        );
+        assert_eq!(
+            scan(&[0x00, 0xFF, 0xF0, 0x00, 0x12, 0x34]).platform(),
+            Some("xochip")
+        );
+    }
+
+    #[test]
+    fn data_is_not_code_test() {
+        //JP 200 followed by a sprite that reads as F002.
+        assert_eq!(scan(&[0x12, 0x00, 0xF0, 0x02]).platform(), None);
+        //Called subroutines are scanned: CALL 204, JP 202, PITCH V0, RET.
+        let program = [0x22, 0x04, 0x12, 0x02, 0xF0, 0x3A, 0x00, 0xEE];
+        assert_eq!(scan(&program).platform(), Some("xochip"));
+    }
+
+    #[test]
+    fn bundled_roms_test() {
+        for rom in &["demo.ch8", "space.ch8"] {
+            let path = format!("{}/res/{}", env!("CARGO_MANIFEST_DIR"), rom);
+            assert_eq!(
+                scan(&std::fs::read(path).unwrap()).platform(),
+                None,
+                "{}",
+                rom
+            );
+        }
+    }
+
+    #[test]
+    fn identify_test() {
+        let profile = Database::embedded()
+            .identify("ffff", &[0x00, 0xFE, 0x12, 0x00])
+            .unwrap();
+        assert_eq!(profile.source, Source::Heuristic);
+        assert_eq!(profile.quirks, Some(Quirks::schip()));
+        assert_eq!(
+            profile.to_string(),
+            "Looks like a superchip program (unsupported platform superchip)"
+        );
+        assert_eq!(profile.layer(), Layer::default());
+        //FX30 is the only SCHIP opcode here, and the CPU has it.
+        let profile = Database::embedded()
+            .identify("ffff", &[0xF0, 0x30, 0x12, 0x00])
+            .unwrap();
+        assert!(profile.supported);
+        assert_eq!(profile.to_string(), "Looks like a superchip program");
+        assert_eq!(profile.layer().quirks, Some(Quirks::schip()));
+    }
+}
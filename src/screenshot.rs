@@ -0,0 +1,44 @@
+use crate::framebuffer::PixelBuffer;
+use crate::palette::Palette;
+use snafu::{ResultExt, Snafu};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Snafu)]
+pub enum ScreenshotError {
+    #[snafu(display("Could not create screenshot {}: {}", filename.display(), source))]
+    CreateError {
+        filename: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not encode screenshot {}: {}", filename.display(), source))]
+    EncodeError {
+        filename: PathBuf,
+        source: png::EncodingError,
+    },
+}
+
+//Write the pixel buffer to a PNG file, every pixel a scale x scale square.
+pub fn save_png<T: AsRef<Path>>(
+    pixels: &PixelBuffer,
+    palette: &Palette,
+    scale: u32,
+    path: T,
+) -> Result<(), ScreenshotError> {
+    let filename = path.as_ref();
+    let image = palette.render(pixels, scale);
+    let file = File::create(filename).context(CreateError {
+        filename: filename.to_path_buf(),
+    })?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width, image.height);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&image.data))
+        .context(EncodeError {
+            filename: filename.to_path_buf(),
+        })
+}
@@ -0,0 +1,523 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+//Time to fade the beeper in or out, long enough to avoid clicks.
+const RAMP_SECONDS: f32 = 0.005;
+
+//Timer ticks per second, the unit of emulated time.
+pub const TICK_RATE: f64 = 60.0;
+//How far playback trails the emulator, in ticks.
+const LATENCY_TICKS: f64 = 2.0;
+//Playback further behind than this jumps forward, e.g. when fast-forwarding.
+const MAX_LAG_TICKS: f64 = 6.0;
+
+//XO-CHIP 1-bit sample buffer, 128 bits played MSB first.
+pub const PATTERN_BYTES: usize = 16;
+const PATTERN_BITS: f64 = PATTERN_BYTES as f64 * 8.0;
+//Pitch 64 plays the pattern at 4000 bits per second.
+pub const DEFAULT_PITCH: u8 = 64;
+
+//Bits per second for an XO-CHIP pitch value.
+pub fn pattern_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    //White noise, resampled at the tone frequency.
+    Noise,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            "noise" => Ok(Waveform::Noise),
+            _ => Err(format!(
+                "Unknown waveform {}, use square, sine, triangle or noise",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Waveform {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Waveform::Square => "square",
+            Waveform::Sine => "sine",
+            Waveform::Triangle => "triangle",
+            Waveform::Noise => "noise",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//What the beeper sounds like.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub waveform: Waveform,
+    pub frequency: f32,
+    //0 to 1.
+    pub volume: f32,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.25,
+        }
+    }
+}
+
+//Beeper sample generator, independent of the audio backend.
+pub struct Synth {
+    tone: Tone,
+    sample_rate: f32,
+    phase: f32,
+    level: f32,
+    gate: bool,
+    muted: bool,
+    noise: u32,
+    noise_value: f32,
+    //Replaces the tone once a program loads one.
+    pattern: Option<[u8; PATTERN_BYTES]>,
+    pitch: u8,
+    pattern_position: f64,
+}
+
+impl Synth {
+    pub fn new(tone: Tone, sample_rate: u32) -> Synth {
+        Synth {
+            tone,
+            sample_rate: sample_rate as f32,
+            phase: 0.0,
+            level: 0.0,
+            gate: false,
+            muted: false,
+            noise: 0x1234_5678,
+            noise_value: 1.0,
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+            pattern_position: 0.0,
+        }
+    }
+
+    pub fn tone(&self) -> Tone {
+        self.tone
+    }
+
+    pub fn set_tone(&mut self, tone: Tone) {
+        self.tone = tone;
+    }
+
+    //Beeper on or off, the output fades to the new state.
+    pub fn set_gate(&mut self, on: bool) {
+        self.gate = on;
+    }
+
+    pub fn set_pattern(&mut self, pattern: Option<[u8; PATTERN_BYTES]>, pitch: u8) {
+        self.pattern = pattern;
+        self.pitch = pitch;
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let target = if self.gate && !self.muted { 1.0 } else { 0.0 };
+        let ramp_step = 1.0 / (RAMP_SECONDS * self.sample_rate);
+        if self.level < target {
+            self.level = (self.level + ramp_step).min(target);
+        } else if self.level > target {
+            self.level = (self.level - ramp_step).max(target);
+        }
+        if self.level == 0.0 {
+            //Start the next beep at the beginning of a cycle.
+            self.phase = 0.0;
+            self.pattern_position = 0.0;
+            return 0.0;
+        }
+        let value = match self.pattern {
+            Some(pattern) => self.pattern_sample(&pattern),
+            None => {
+                let value = self.oscillator();
+                self.advance_phase();
+                value
+            }
+        };
+        value * self.tone.volume * self.level
+    }
+
+    fn pattern_sample(&mut self, pattern: &[u8; PATTERN_BYTES]) -> f32 {
+        let bit = self.pattern_position as usize;
+        let value = match pattern[bit / 8] & (0x80 >> (bit % 8)) {
+            0 => -1.0,
+            _ => 1.0,
+        };
+        self.pattern_position += pattern_rate(self.pitch) / self.sample_rate as f64;
+        self.pattern_position %= PATTERN_BITS;
+        value
+    }
+
+    fn oscillator(&self) -> f32 {
+        let phase = self.phase;
+        match self.tone.waveform {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Noise => self.noise_value,
+        }
+    }
+
+    fn advance_phase(&mut self) {
+        self.phase += self.tone.frequency / self.sample_rate;
+        if self.phase >= 1.0 {
+            self.phase %= 1.0;
+            //xorshift32, new noise value once per cycle.
+            self.noise ^= self.noise << 13;
+            self.noise ^= self.noise >> 17;
+            self.noise ^= self.noise << 5;
+            self.noise_value = if self.noise & 1 == 1 { 1.0 } else { -1.0 };
+        }
+    }
+}
+
+//Everything the program controls about the sound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoundState {
+    pub on: bool,
+    pub pattern: Option<[u8; PATTERN_BYTES]>,
+    pub pitch: u8,
+}
+
+impl Default for SoundState {
+    fn default() -> Self {
+        SoundState {
+            on: false,
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoundChange {
+    Beeper(bool),
+    Pattern(Option<[u8; PATTERN_BYTES]>),
+    Pitch(u8),
+}
+
+//Sound change at a point in emulated time, counted in timer ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoundEvent {
+    pub time: f64,
+    pub change: SoundChange,
+}
+
+impl SoundState {
+    pub fn apply(&mut self, change: SoundChange) {
+        match change {
+            SoundChange::Beeper(on) => self.on = on,
+            SoundChange::Pattern(pattern) => self.pattern = pattern,
+            SoundChange::Pitch(pitch) => self.pitch = pitch,
+        }
+    }
+
+    //Changes that turn this state into the other one.
+    pub fn changes_to(&self, other: &SoundState) -> Vec<SoundChange> {
+        let mut changes = Vec::new();
+        if self.pattern != other.pattern {
+            changes.push(SoundChange::Pattern(other.pattern));
+        }
+        if self.pitch != other.pitch {
+            changes.push(SoundChange::Pitch(other.pitch));
+        }
+        if self.on != other.on {
+            changes.push(SoundChange::Beeper(other.on));
+        }
+        changes
+    }
+}
+
+//Plays sound events back sample by sample, a little behind the emulator.
+pub struct SoundTimeline {
+    events: VecDeque<SoundEvent>,
+    //None until the emulator reports its time for the first time.
+    position: Option<f64>,
+    emulated: f64,
+    ticks_per_sample: f64,
+    latency: f64,
+    state: SoundState,
+}
+
+impl SoundTimeline {
+    pub fn new(sample_rate: u32) -> SoundTimeline {
+        SoundTimeline {
+            events: VecDeque::new(),
+            position: None,
+            emulated: 0.0,
+            ticks_per_sample: TICK_RATE / sample_rate as f64,
+            latency: LATENCY_TICKS,
+            state: SoundState::default(),
+        }
+    }
+
+    //Offline rendering can play right up to the emulator, without any latency.
+    pub fn set_latency(&mut self, ticks: f64) {
+        self.latency = ticks;
+    }
+
+    //New events and the time the emulator has reached.
+    pub fn push(&mut self, events: &[SoundEvent], now: f64) {
+        self.events.extend(events.iter().copied());
+        self.emulated = now;
+        let behind = match self.position {
+            Some(position) => now - position > MAX_LAG_TICKS,
+            None => true,
+        };
+        if behind {
+            let position = now - self.latency;
+            self.position = Some(position);
+            self.apply_until(position);
+        }
+    }
+
+    //Sound state for the next sample. Playback waits when it catches up with the emulator.
+    pub fn next_state(&mut self) -> &SoundState {
+        if let Some(position) = self.position {
+            let position = (position + self.ticks_per_sample).min(self.emulated);
+            self.position = Some(position);
+            self.apply_until(position);
+        }
+        &self.state
+    }
+
+    fn apply_until(&mut self, position: f64) {
+        while let Some(event) = self.events.front() {
+            if event.time > position {
+                break;
+            }
+            self.state.apply(event.change);
+            self.events.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sound::{
+        pattern_rate, SoundChange, SoundEvent, SoundState, SoundTimeline, Synth, Tone, Waveform,
+        DEFAULT_PITCH,
+    };
+
+    const RATE: u32 = 44_100;
+    //Samples in a full fade.
+    const RAMP: usize = 221;
+
+    fn synth(waveform: Waveform) -> Synth {
+        let tone = Tone {
+            waveform,
+            frequency: 441.0,
+            volume: 0.5,
+        };
+        Synth::new(tone, RATE)
+    }
+
+    fn samples(synth: &mut Synth, count: usize) -> Vec<f32> {
+        let mut out = vec![0.0; count];
+        synth.fill(&mut out);
+        out
+    }
+
+    #[test]
+    fn waveform_parse_test() {
+        assert_eq!("sine".parse(), Ok(Waveform::Sine));
+        assert_eq!("noise".parse(), Ok(Waveform::Noise));
+        assert!("saw".parse::<Waveform>().is_err());
+    }
+
+    #[test]
+    fn silent_without_gate_test() {
+        let mut synth = synth(Waveform::Square);
+        assert!(samples(&mut synth, 100).iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn fade_in_test() {
+        let mut synth = synth(Waveform::Square);
+        synth.set_gate(true);
+        let out = samples(&mut synth, RAMP + 10);
+        assert!(out[0].abs() < 0.01);
+        assert!(out[RAMP / 2].abs() < 0.5);
+        assert_eq!(out[RAMP + 5].abs(), 0.5);
+    }
+
+    #[test]
+    fn fade_out_test() {
+        let mut synth = synth(Waveform::Square);
+        synth.set_gate(true);
+        samples(&mut synth, RAMP + 10);
+        synth.set_gate(false);
+        let out = samples(&mut synth, RAMP + 10);
+        assert!(out[0].abs() > 0.45);
+        assert_eq!(out[RAMP + 5], 0.0);
+    }
+
+    #[test]
+    fn mute_test() {
+        let mut synth = synth(Waveform::Square);
+        synth.set_gate(true);
+        synth.set_muted(true);
+        samples(&mut synth, RAMP);
+        assert!(samples(&mut synth, 100).iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn frequency_test() {
+        //441 Hz at 44.1 kHz is 100 samples per cycle.
+        for &waveform in &[Waveform::Square, Waveform::Sine, Waveform::Triangle] {
+            let mut synth = synth(waveform);
+            synth.set_gate(true);
+            samples(&mut synth, RAMP);
+            let out = samples(&mut synth, 1000);
+            let rising = out
+                .windows(2)
+                .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+                .count();
+            assert_eq!(rising, 10, "{:?}", waveform);
+            assert!(out.iter().all(|s| s.abs() <= 0.5));
+        }
+    }
+
+    fn beep(on: f64, off: f64) -> Vec<SoundEvent> {
+        vec![
+            SoundEvent {
+                time: on,
+                change: SoundChange::Beeper(true),
+            },
+            SoundEvent {
+                time: off,
+                change: SoundChange::Beeper(false),
+            },
+        ]
+    }
+
+    //Samples with the gate on while playing the timeline for some ticks.
+    fn gate_samples(timeline: &mut SoundTimeline, ticks: usize) -> usize {
+        (0..ticks * RATE as usize / 60)
+            .filter(|_| timeline.next_state().on)
+            .count()
+    }
+
+    #[test]
+    fn one_tick_beep_test() {
+        let mut timeline = SoundTimeline::new(RATE);
+        timeline.push(&[], 0.0);
+        timeline.push(&beep(0.5, 1.5), 4.0);
+        //One tick at 44.1 kHz is 735 samples.
+        let on = gate_samples(&mut timeline, 6) as i32;
+        assert!((on - 735).abs() <= 1, "{}", on);
+    }
+
+    #[test]
+    fn playback_waits_for_emulator_test() {
+        let mut timeline = SoundTimeline::new(RATE);
+        timeline.push(&[], 10.0);
+        timeline.push(
+            &[SoundEvent {
+                time: 10.5,
+                change: SoundChange::Beeper(true),
+            }],
+            11.0,
+        );
+        //Starts at tick 8 and stops at 11, the beep keeps going until told otherwise.
+        assert_eq!(gate_samples(&mut timeline, 10), 10 * 735 - 5 * 735 / 2);
+    }
+
+    #[test]
+    fn fast_forward_resync_test() {
+        let mut timeline = SoundTimeline::new(RATE);
+        timeline.push(&[], 0.0);
+        let mut events = beep(1.0, 2.0);
+        events.extend(beep(20.0, 29.0));
+        timeline.push(&events, 30.0);
+        //Jumped to 28: the first beep is skipped, the second one is playing.
+        assert!(timeline.next_state().on);
+        assert_eq!(gate_samples(&mut timeline, 4) + 1, 735);
+    }
+
+    #[test]
+    fn pattern_rate_test() {
+        assert_eq!(pattern_rate(DEFAULT_PITCH), 4000.0);
+        assert!((pattern_rate(112) - 8000.0).abs() < 1e-9);
+        assert!((pattern_rate(16) - 2000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pattern_playback_test() {
+        //First byte all ones, the rest zero. At pitch 64 and 4000 Hz a bit lasts one sample.
+        let mut pattern = [0u8; 16];
+        pattern[0] = 0xFF;
+        let mut synth = Synth::new(Tone::default(), 4000);
+        synth.set_pattern(Some(pattern), DEFAULT_PITCH);
+        synth.set_gate(true);
+        samples(&mut synth, 128 * 10);
+        let out = samples(&mut synth, 128);
+        assert!(out[..8].iter().all(|s| *s > 0.0));
+        assert!(out[8..].iter().all(|s| *s < 0.0));
+    }
+
+    #[test]
+    fn state_changes_test() {
+        let from = SoundState::default();
+        let to = SoundState {
+            on: true,
+            pattern: Some([0xAA; 16]),
+            pitch: 100,
+        };
+        let changes = from.changes_to(&to);
+        assert_eq!(
+            changes,
+            vec![
+                SoundChange::Pattern(Some([0xAA; 16])),
+                SoundChange::Pitch(100),
+                SoundChange::Beeper(true),
+            ]
+        );
+        let mut state = from;
+        for change in changes {
+            state.apply(change);
+        }
+        assert_eq!(state, to);
+        assert!(to.changes_to(&to).is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+use crate::cpu::CpuState;
+use crate::framebuffer::{PixelBuffer, COLUMNS, ROWS};
+use crate::mem::{MAX_MEM_SIZE, MEM_SIZE};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"C8SS";
+//Version 1 always had 4K of memory, 2 stores its size.
+const VERSION: u8 = 2;
+
+#[derive(Debug, Snafu)]
+pub enum StateError {
+    #[snafu(display("Could not read save state {}: {}", filename.display(), source))]
+    ReadError {
+        filename: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not write save state {}: {}", filename.display(), source))]
+    WriteError {
+        filename: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Not a save state"))]
+    NotAState,
+    #[snafu(display("Save state version {} is not supported", version))]
+    UnsupportedVersion { version: u8 },
+    #[snafu(display("Save state is truncated"))]
+    Truncated,
+    #[snafu(display("Save state has an unsupported {}x{} screen", columns, rows))]
+    BadScreenSize { columns: usize, rows: usize },
+}
+
+//Snapshot of a running emulator. Quirks and speed are configuration and are not saved.
+//Layout, big endian: magic, version, CPU, ticks, memory size, memory, screen size,
+//one byte per pixel.
+#[derive(Debug, Clone)]
+pub struct SaveState {
+    pub cpu: CpuState,
+    pub ticks: u64,
+    pub memory: Vec<u8>,
+    pub pixels: PixelBuffer,
+}
+
+impl SaveState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let cpu = &self.cpu;
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        out.extend_from_slice(&cpu.regs);
+        out.extend_from_slice(&cpu.i.to_be_bytes());
+        out.extend_from_slice(&cpu.pc.to_be_bytes());
+        out.push(cpu.dt);
+        out.push(cpu.st);
+        for address in cpu.stack.iter() {
+            out.extend_from_slice(&address.to_be_bytes());
+        }
+        out.push(cpu.sp as u8);
+        out.extend_from_slice(&cpu.rng.to_be_bytes());
+        out.push(cpu.audio_pattern.is_some() as u8);
+        out.extend_from_slice(&cpu.audio_pattern.unwrap_or_default());
+        out.push(cpu.pitch);
+        out.extend_from_slice(&self.ticks.to_be_bytes());
+        out.extend_from_slice(&(self.memory.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.memory);
+        let (columns, rows) = (self.pixels.columns(), self.pixels.rows());
+        out.extend_from_slice(&(columns as u16).to_be_bytes());
+        out.extend_from_slice(&(rows as u16).to_be_bytes());
+        for row in 0..rows {
+            for column in 0..columns {
+                out.push(self.pixels.get(column, row) as u8);
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SaveState, StateError> {
+        let mut reader = Reader { bytes };
+        ensure!(reader.take(MAGIC.len())? == MAGIC, NotAState);
+        let version = reader.u8()?;
+        ensure!(
+            version == 1 || version == VERSION,
+            UnsupportedVersion { version }
+        );
+
+        let mut regs = [0; 16];
+        regs.copy_from_slice(reader.take(16)?);
+        let i = reader.u16()?;
+        let pc = reader.u16()?;
+        let dt = reader.u8()?;
+        let st = reader.u8()?;
+        let mut stack = [0; 16];
+        for address in stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        let sp = reader.u8()? as usize;
+        let rng = reader.u32()?;
+        let has_pattern = reader.u8()? != 0;
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(reader.take(16)?);
+        let pitch = reader.u8()?;
+        let ticks = reader.u64()?;
+        let size = match version {
+            1 => MEM_SIZE,
+            _ => (reader.u32()? as usize).min(MAX_MEM_SIZE),
+        };
+        let memory = reader.take(size)?.to_vec();
+
+        let columns = reader.u16()? as usize;
+        let rows = reader.u16()? as usize;
+        ensure!(
+            (1..=COLUMNS).contains(&columns) && (1..=ROWS).contains(&rows),
+            BadScreenSize { columns, rows }
+        );
+        let data = reader.take(columns * rows)?;
+        let mut pixels = PixelBuffer::new(columns, rows);
+        for (index, &on) in data.iter().enumerate() {
+            pixels.set(index % columns, index / columns, on != 0);
+        }
+
+        Ok(SaveState {
+            cpu: CpuState {
+                regs,
+                i,
+                pc,
+                dt,
+                st,
+                stack,
+                sp,
+                rng,
+                audio_pattern: if has_pattern { Some(pattern) } else { None },
+                pitch,
+            },
+            ticks,
+            memory,
+            pixels,
+        })
+    }
+
+    pub fn load<T: AsRef<Path>>(path: T) -> Result<SaveState, StateError> {
+        let filename = path.as_ref();
+        let bytes = fs::read(filename).context(ReadError { filename })?;
+        SaveState::from_bytes(&bytes)
+    }
+
+    pub fn save<T: AsRef<Path>>(&self, path: T) -> Result<(), StateError> {
+        let filename = path.as_ref();
+        fs::write(filename, self.to_bytes()).context(WriteError { filename })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], StateError> {
+        let taken = self.bytes.get(..count).context(Truncated)?;
+        self.bytes = &self.bytes[count..];
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::emulator::Emulator;
+    use crate::memmap::MemoryMap;
+    use crate::rom::Rom;
+    use crate::state::{SaveState, StateError};
+
+    //LD V1, 05; LD DT, V1; DRW V0, V0, 5 (the font 0 glyph); CALL 200.
+    fn emulator() -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator
+            .load(&Rom::from_bytes(&[0x61, 0x05, 0xF1, 0x15, 0xD0, 0x05, 0x22, 0x00]).unwrap())
+            .unwrap();
+        emulator
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let mut emulator = emulator();
+        emulator.run_frame().unwrap();
+        let state = emulator.save_state();
+        let bytes = state.to_bytes();
+        let loaded = SaveState::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.cpu, state.cpu);
+        assert_eq!(loaded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn resume_test() {
+        let mut original = emulator();
+        original.run_frames(2).unwrap();
+        let mut resumed = Emulator::new();
+        resumed.load_state(&SaveState::from_bytes(&original.save_state().to_bytes()).unwrap());
+        assert!(resumed.pixels().get(0, 0));
+        original.run_frame().unwrap();
+        resumed.run_frame().unwrap();
+        assert_eq!(
+            resumed.save_state().to_bytes(),
+            original.save_state().to_bytes()
+        );
+    }
+
+    #[test]
+    fn version_1_test() {
+        let mut emulator = emulator();
+        emulator.run_frame().unwrap();
+        let state = emulator.save_state();
+        //Version 1 had no memory size after the CPU and ticks.
+        let mut old = state.to_bytes();
+        old[4] = 1;
+        old.drain(90..94);
+        let loaded = SaveState::from_bytes(&old).unwrap();
+        assert_eq!(loaded.memory, state.memory);
+        assert_eq!(loaded.cpu, state.cpu);
+    }
+
+    #[test]
+    fn memory_size_test() {
+        let mut emulator = Emulator::new();
+        emulator.set_memory_map(MemoryMap::xochip());
+        emulator
+            .load(&Rom::from_bytes(&[0x12, 0x00]).unwrap())
+            .unwrap();
+        let state = SaveState::from_bytes(&emulator.save_state().to_bytes()).unwrap();
+        assert_eq!(state.memory.len(), 0x10000);
+        let mut resumed = Emulator::new();
+        resumed.load_state(&state);
+        assert_eq!(resumed.save_state().memory.len(), 0x10000);
+    }
+
+    #[test]
+    fn bad_state_test() {
+        let bytes = emulator().save_state().to_bytes();
+        assert!(matches!(
+            SaveState::from_bytes(b"PNG!"),
+            Err(StateError::NotAState)
+        ));
+        let mut newer = bytes.clone();
+        newer[4] = 99;
+        assert!(matches!(
+            SaveState::from_bytes(&newer),
+            Err(StateError::UnsupportedVersion { version: 99 })
+        ));
+        assert!(matches!(
+            SaveState::from_bytes(&bytes[..100]),
+            Err(StateError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn bad_screen_size_test() {
+        let bytes = emulator().save_state().to_bytes();
+        //The screen size comes right before the 64x32 pixels.
+        let size = bytes.len() - 64 * 32 - 4;
+        let mut empty = bytes.clone();
+        empty[size..size + 4].copy_from_slice(&[0, 0, 0, 32]);
+        assert!(matches!(
+            SaveState::from_bytes(&empty),
+            Err(StateError::BadScreenSize {
+                columns: 0,
+                rows: 32
+            })
+        ));
+        let mut huge = bytes;
+        huge[size..size + 4].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(matches!(
+            SaveState::from_bytes(&huge),
+            Err(StateError::BadScreenSize { .. })
+        ));
+    }
+}
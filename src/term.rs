@@ -0,0 +1,155 @@
+use crate::framebuffer::PixelBuffer;
+use std::fmt::Write;
+use std::str::FromStr;
+
+//How pixels are packed into terminal character cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TermMode {
+    //One cell holds 1x2 pixels using ▀ ▄ █.
+    HalfBlock,
+    //One cell holds 2x4 pixels using braille dots.
+    Braille,
+}
+
+impl FromStr for TermMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "halfblock" => Ok(TermMode::HalfBlock),
+            "braille" => Ok(TermMode::Braille),
+            _ => Err(format!(
+                "Unknown terminal mode {}, use halfblock or braille",
+                s
+            )),
+        }
+    }
+}
+
+//Turns a pixel buffer into text with 24-bit ANSI colour escapes.
+pub struct TermRenderer {
+    mode: TermMode,
+    foreground: (u8, u8, u8),
+    background: (u8, u8, u8),
+}
+
+impl TermRenderer {
+    pub fn new(mode: TermMode) -> TermRenderer {
+        TermRenderer {
+            mode,
+            foreground: (255, 255, 255),
+            background: (0, 0, 0),
+        }
+    }
+
+    pub fn set_colors(&mut self, foreground: (u8, u8, u8), background: (u8, u8, u8)) {
+        self.foreground = foreground;
+        self.background = background;
+    }
+
+    //Size of the rendered frame in character cells.
+    pub fn size(&self, pixels: &PixelBuffer) -> (usize, usize) {
+        let (width, height) = self.cell_size();
+        (
+            pixels.columns().div_ceil(width),
+            pixels.rows().div_ceil(height),
+        )
+    }
+
+    fn cell_size(&self) -> (usize, usize) {
+        match self.mode {
+            TermMode::HalfBlock => (1, 2),
+            TermMode::Braille => (2, 4),
+        }
+    }
+
+    //Lines are separated with \r\n so the output also works in raw mode.
+    pub fn render(&self, pixels: &PixelBuffer) -> String {
+        let (columns, rows) = self.size(pixels);
+        let (fg, bg) = (self.foreground, self.background);
+        let mut out = String::new();
+        for row in 0..rows {
+            let _ = write!(
+                out,
+                "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                fg.0, fg.1, fg.2, bg.0, bg.1, bg.2
+            );
+            for column in 0..columns {
+                out.push(match self.mode {
+                    TermMode::HalfBlock => half_block(pixels, column, row),
+                    TermMode::Braille => braille(pixels, column, row),
+                });
+            }
+            out.push_str("\x1b[0m\r\n");
+        }
+        out
+    }
+}
+
+fn half_block(pixels: &PixelBuffer, column: usize, row: usize) -> char {
+    let top = pixels.get(column, row * 2);
+    let bottom = pixels.get(column, row * 2 + 1);
+    match (top, bottom) {
+        (false, false) => ' ',
+        (true, false) => '▀',
+        (false, true) => '▄',
+        (true, true) => '█',
+    }
+}
+
+fn braille(pixels: &PixelBuffer, column: usize, row: usize) -> char {
+    //Dot numbering of the unicode braille block, (x, y) -> bit.
+    const DOTS: [(usize, usize, u32); 8] = [
+        (0, 0, 0x01),
+        (0, 1, 0x02),
+        (0, 2, 0x04),
+        (1, 0, 0x08),
+        (1, 1, 0x10),
+        (1, 2, 0x20),
+        (0, 3, 0x40),
+        (1, 3, 0x80),
+    ];
+    let (x, y) = (column * 2, row * 4);
+    let bits = DOTS
+        .iter()
+        .filter(|(dx, dy, _)| pixels.get(x + dx, y + dy))
+        .fold(0, |acc, (_, _, bit)| acc | bit);
+    std::char::from_u32(0x2800 + bits).unwrap_or(' ')
+}
+
+#[cfg(test)]
+mod test {
+    use crate::framebuffer::{PixelBuffer, Sprite};
+    use crate::term::{TermMode, TermRenderer};
+
+    fn strip_colors(text: &str) -> String {
+        let mut out = String::new();
+        let mut escape = false;
+        for c in text.chars() {
+            match c {
+                '\x1b' => escape = true,
+                'm' if escape => escape = false,
+                _ if escape => {}
+                '\r' => {}
+                _ => out.push(c),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn half_block_test() {
+        let mut pixels = PixelBuffer::new(4, 4);
+        pixels.add_sprite(0, 0, Sprite::new(&[0xC0, 0x60, 0x00, 0xF0]));
+        let text = TermRenderer::new(TermMode::HalfBlock).render(&pixels);
+        assert_eq!(strip_colors(&text), "▀█▄ \n▄▄▄▄\n");
+    }
+
+    #[test]
+    fn braille_test() {
+        let mut pixels = PixelBuffer::new(4, 4);
+        pixels.add_sprite(0, 0, Sprite::new(&[0x80, 0x40, 0x00, 0x30]));
+        let text = TermRenderer::new(TermMode::Braille).render(&pixels);
+        assert_eq!(strip_colors(&text), "\u{2811}\u{28C0}\n");
+    }
+}
@@ -0,0 +1,292 @@
+use crate::cpu::Cpu;
+use crate::disasm::{disassemble_with, Symbols};
+use crate::mem::Memory;
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+//State of the machine right before an instruction runs. One line of a trace:
+//PC=0200 OP=6A0F V=00,00,...,00 I=0000 SP=0 DT=00 ST=00 ; LD VA, 0F
+//Everything before the ';' is compared, the mnemonic is only there for people.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: usize,
+    pub dt: u8,
+    pub st: u8,
+}
+
+impl TraceEntry {
+    pub fn new(cpu: &Cpu, memory: &Memory) -> TraceEntry {
+        let bytes = [
+            memory.read_8(cpu.pc()),
+            memory.read_8(cpu.pc().wrapping_add(1)),
+        ];
+        let mut v = [0; 16];
+        for (reg, value) in v.iter_mut().enumerate() {
+            *value = cpu.reg(reg as u8);
+        }
+        TraceEntry {
+            pc: cpu.pc(),
+            opcode: u16::from_be_bytes([bytes[0], bytes[1]]),
+            v,
+            i: cpu.i(),
+            sp: cpu.stack_depth(),
+            dt: cpu.dt(),
+            st: cpu.st(),
+        }
+    }
+
+    //The Display line, with label names in the comment: "; main: JP loop".
+    pub fn to_line(&self, symbols: &Symbols) -> String {
+        let v: Vec<String> = self
+            .v
+            .iter()
+            .map(|value| format!("{:02X}", value))
+            .collect();
+        let mnemonic = disassemble_with(self.opcode, symbols);
+        format!(
+            "PC={:04X} OP={:04X} V={} I={:04X} SP={} DT={:02X} ST={:02X} ; {}",
+            self.pc,
+            self.opcode,
+            v.join(","),
+            self.i,
+            self.sp,
+            self.dt,
+            self.st,
+            match symbols.name(self.pc) {
+                Some(label) => format!("{}: {}", label, mnemonic),
+                None => mnemonic,
+            }
+        )
+    }
+
+    //Fields that differ from the other entry, as "NAME ours -> theirs".
+    pub fn delta(&self, other: &TraceEntry) -> Vec<String> {
+        let mut delta = Vec::new();
+        if self.pc != other.pc {
+            delta.push(format!("PC {:04X} -> {:04X}", self.pc, other.pc));
+        }
+        if self.opcode != other.opcode {
+            delta.push(format!("OP {:04X} -> {:04X}", self.opcode, other.opcode));
+        }
+        for (reg, (ours, theirs)) in self.v.iter().zip(other.v.iter()).enumerate() {
+            if ours != theirs {
+                delta.push(format!("V{:X} {:02X} -> {:02X}", reg, ours, theirs));
+            }
+        }
+        if self.i != other.i {
+            delta.push(format!("I {:04X} -> {:04X}", self.i, other.i));
+        }
+        if self.sp != other.sp {
+            delta.push(format!("SP {} -> {}", self.sp, other.sp));
+        }
+        if self.dt != other.dt {
+            delta.push(format!("DT {:02X} -> {:02X}", self.dt, other.dt));
+        }
+        if self.st != other.st {
+            delta.push(format!("ST {:02X} -> {:02X}", self.st, other.st));
+        }
+        delta
+    }
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.to_line(&Symbols::default()))
+    }
+}
+
+//Reads a line written by Display, the mnemonic after ';' is optional and ignored.
+impl FromStr for TraceEntry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let state = s.split(';').next().unwrap_or("");
+        let mut entry = TraceEntry {
+            pc: 0,
+            opcode: 0,
+            v: [0; 16],
+            i: 0,
+            sp: 0,
+            dt: 0,
+            st: 0,
+        };
+        let mut seen = Vec::new();
+        for field in state.split_whitespace() {
+            let mut parts = field.splitn(2, '=');
+            let name = parts.next().unwrap_or("");
+            let value = parts
+                .next()
+                .ok_or_else(|| format!("Expected NAME=VALUE, got {}", field))?;
+            let hex = |value: &str| {
+                u16::from_str_radix(value, 16).map_err(|_| format!("Bad value in {}", field))
+            };
+            let byte = |value: &str| {
+                u8::try_from(hex(value)?).map_err(|_| format!("{} does not fit in a byte", field))
+            };
+            match name {
+                "PC" => entry.pc = hex(value)?,
+                "OP" => entry.opcode = hex(value)?,
+                "I" => entry.i = hex(value)?,
+                "SP" => {
+                    entry.sp = value
+                        .parse()
+                        .map_err(|_| format!("Bad value in {}", field))?
+                }
+                "DT" => entry.dt = byte(value)?,
+                "ST" => entry.st = byte(value)?,
+                "V" => {
+                    let registers: Vec<&str> = value.split(',').collect();
+                    if registers.len() != 16 {
+                        return Err(format!("Expected 16 registers, got {}", registers.len()));
+                    }
+                    for (reg, register) in registers.iter().enumerate() {
+                        entry.v[reg] = byte(register)?;
+                    }
+                }
+                _ => return Err(format!("Unknown field {}", name)),
+            }
+            seen.push(name);
+        }
+        for name in &["PC", "OP", "V", "I", "SP", "DT", "ST"] {
+            if !seen.contains(name) {
+                return Err(format!("Missing field {}", name));
+            }
+        }
+        Ok(entry)
+    }
+}
+
+//Index of the first entry where the traces disagree, None if they are identical.
+//A trace that stops early diverges where it ends.
+pub fn first_divergence(ours: &[TraceEntry], theirs: &[TraceEntry]) -> Option<usize> {
+    match ours.iter().zip(theirs.iter()).position(|(a, b)| a != b) {
+        Some(index) => Some(index),
+        None if ours.len() != theirs.len() => Some(ours.len().min(theirs.len())),
+        None => None,
+    }
+}
+
+//Writes one TraceEntry line per executed instruction.
+pub struct TraceWriter {
+    inner: Box<dyn Write>,
+}
+
+impl TraceWriter {
+    pub fn new(inner: Box<dyn Write>) -> TraceWriter {
+        TraceWriter { inner }
+    }
+
+    pub fn record(&mut self, cpu: &Cpu, memory: &Memory, symbols: &Symbols) -> io::Result<()> {
+        writeln!(
+            self.inner,
+            "{}",
+            TraceEntry::new(cpu, memory).to_line(symbols)
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::disasm::Symbols;
+    use crate::trace::{first_divergence, TraceEntry};
+
+    fn entry(pc: u16) -> TraceEntry {
+        TraceEntry {
+            pc,
+            opcode: 0x6A0F,
+            v: [0; 16],
+            i: 0,
+            sp: 0,
+            dt: 0,
+            st: 0,
+        }
+    }
+
+    #[test]
+    fn format_test() {
+        let mut v = [0; 16];
+        v[0xA] = 0x0F;
+        v[0xF] = 1;
+        let entry = TraceEntry {
+            pc: 0x202,
+            opcode: 0x6A0F,
+            v,
+            i: 0x300,
+            sp: 2,
+            dt: 0x3C,
+            st: 0,
+        };
+        assert_eq!(
+            entry.to_string(),
+            "PC=0202 OP=6A0F V=00,00,00,00,00,00,00,00,00,00,0F,00,00,00,00,01 \
+             I=0300 SP=2 DT=3C ST=00 ; LD VA, 0F"
+        );
+    }
+
+    #[test]
+    fn symbols_test() {
+        let mut symbols = Symbols::default();
+        symbols.insert(0x200, "main");
+        let mut entry = entry(0x200);
+        entry.opcode = 0x1200;
+        let line = entry.to_line(&symbols);
+        assert!(line.ends_with("; main: JP main"));
+        assert_eq!(line.parse::<TraceEntry>().unwrap(), entry);
+    }
+
+    #[test]
+    fn parse_round_trip_test() {
+        let mut entry = entry(0x202);
+        entry.v[3] = 0xAB;
+        entry.sp = 12;
+        entry.dt = 0xFF;
+        let parsed: TraceEntry = entry.to_string().parse().unwrap();
+        assert_eq!(parsed, entry);
+        let without_mnemonic = entry.to_string().split(';').next().unwrap().to_string();
+        assert_eq!(without_mnemonic.parse::<TraceEntry>().unwrap(), entry);
+    }
+
+    #[test]
+    fn parse_error_test() {
+        assert!("PC=0200".parse::<TraceEntry>().is_err());
+        assert!("PC=zz OP=0000 V=00 I=0 SP=0 DT=0 ST=0"
+            .parse::<TraceEntry>()
+            .is_err());
+        let mut line = entry(0x200).to_string();
+        line = line.replace("V=00,", "V=");
+        assert!(line.parse::<TraceEntry>().is_err());
+        let line = entry(0x200).to_string().replace("DT=00", "DT=100");
+        assert!(line.parse::<TraceEntry>().is_err());
+        let line = entry(0x200).to_string().replace("V=00,", "V=1FF,");
+        assert!(line.parse::<TraceEntry>().is_err());
+    }
+
+    #[test]
+    fn delta_test() {
+        let ours = entry(0x200);
+        let mut theirs = entry(0x202);
+        theirs.v[0xF] = 1;
+        assert_eq!(ours.delta(&theirs), vec!["PC 0200 -> 0202", "VF 00 -> 01"]);
+        assert!(ours.delta(&ours).is_empty());
+    }
+
+    #[test]
+    fn first_divergence_test() {
+        let ours = vec![entry(0x200), entry(0x202), entry(0x204)];
+        let mut theirs = ours.clone();
+        assert_eq!(first_divergence(&ours, &theirs), None);
+        theirs[2].i = 0x300;
+        assert_eq!(first_divergence(&ours, &theirs), Some(2));
+        assert_eq!(first_divergence(&ours, &ours[..1]), Some(1));
+    }
+}
@@ -0,0 +1,68 @@
+use chip8forever::rom::{Rom, RomError};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+//How often the file is looked at, editors and assemblers need a moment to finish writing.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+//Watches the ROM file by its modification time and loads it again when it changes.
+pub struct RomWatcher {
+    path: PathBuf,
+    entry: Option<String>,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl RomWatcher {
+    pub fn new(path: &Path, entry: Option<&str>) -> RomWatcher {
+        RomWatcher {
+            path: path.to_path_buf(),
+            entry: entry.map(String::from),
+            modified: modified(path),
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    //The new ROM when the file changed since the last poll. A file that is
+    //missing for a moment, as some editors save, is not a change.
+    pub fn poll(&mut self) -> Option<Result<Rom, RomError>> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        let modified = modified(&self.path)?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+        Some(Rom::from_file_entry(&self.path, self.entry.as_deref()))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+//Bytes that differ between two versions of a ROM, counting added or removed ones.
+pub fn changed_bytes(old: &[u8], new: &[u8]) -> usize {
+    let changed = old.iter().zip(new.iter()).filter(|(a, b)| a != b).count();
+    changed + old.len().abs_diff(new.len())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::watch::changed_bytes;
+
+    #[test]
+    fn changed_bytes_test() {
+        assert_eq!(changed_bytes(&[1, 2, 3], &[1, 2, 3]), 0);
+        assert_eq!(changed_bytes(&[1, 2, 3], &[1, 9, 3]), 1);
+        assert_eq!(changed_bytes(&[1, 2, 3], &[1, 2, 3, 4, 5]), 2);
+        assert_eq!(changed_bytes(&[1, 2, 3], &[7]), 3);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::sound::{SoundEvent, SoundTimeline, Synth, Tone, TICK_RATE};
+use snafu::{ResultExt, Snafu};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+const HEADER_BYTES: u32 = 44;
+
+#[derive(Debug, Snafu)]
+pub enum WavError {
+    #[snafu(display("Could not create WAV file {}: {}", filename.display(), source))]
+    CreateError {
+        filename: PathBuf,
+        source: io::Error,
+    },
+    #[snafu(display("Could not write WAV file: {}", source))]
+    WriteError { source: io::Error },
+}
+
+//Mono 16 bit PCM. Sizes in the header are filled in by finish.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    sample_rate: u32,
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(inner: W, sample_rate: u32) -> Result<WavWriter<W>, WavError> {
+        let mut writer = WavWriter {
+            inner,
+            sample_rate,
+            samples: 0,
+        };
+        writer.write_header().context(WriteError)?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let data_bytes = self.samples * 2;
+        let w = &mut self.inner;
+        w.write_all(b"RIFF")?;
+        w.write_all(&(HEADER_BYTES - 8 + data_bytes).to_le_bytes())?;
+        w.write_all(b"WAVEfmt ")?;
+        w.write_all(&16u32.to_le_bytes())?;
+        w.write_all(&1u16.to_le_bytes())?; // PCM
+        w.write_all(&1u16.to_le_bytes())?; // mono
+        w.write_all(&self.sample_rate.to_le_bytes())?;
+        w.write_all(&(self.sample_rate * 2).to_le_bytes())?; // bytes per second
+        w.write_all(&2u16.to_le_bytes())?; // bytes per frame
+        w.write_all(&16u16.to_le_bytes())?; // bits per sample
+        w.write_all(b"data")?;
+        w.write_all(&data_bytes.to_le_bytes())
+    }
+
+    //Samples from -1 to 1, anything outside is clipped.
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<(), WavError> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.inner
+                .write_all(&value.to_le_bytes())
+                .context(WriteError)?;
+        }
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    //Patches the header and hands back the inner writer.
+    pub fn finish(mut self) -> Result<W, WavError> {
+        self.inner.seek(SeekFrom::Start(0)).context(WriteError)?;
+        self.write_header().context(WriteError)?;
+        self.inner.seek(SeekFrom::End(0)).context(WriteError)?;
+        self.inner.flush().context(WriteError)?;
+        Ok(self.inner)
+    }
+}
+
+//Renders the emulator's sound events to WAV in lockstep with emulated time.
+pub struct WavRecorder<W: Write + Seek> {
+    writer: WavWriter<W>,
+    synth: Synth,
+    timeline: SoundTimeline,
+    start: f64,
+}
+
+impl WavRecorder<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        filename: P,
+        tone: Tone,
+        start: f64,
+    ) -> Result<WavRecorder<BufWriter<File>>, WavError> {
+        let filename = filename.as_ref();
+        let file = File::create(filename).context(CreateError { filename })?;
+        WavRecorder::new(BufWriter::new(file), tone, start)
+    }
+}
+
+impl<W: Write + Seek> WavRecorder<W> {
+    //Start is the emulator time the recording begins at.
+    pub fn new(inner: W, tone: Tone, start: f64) -> Result<WavRecorder<W>, WavError> {
+        let mut timeline = SoundTimeline::new(SAMPLE_RATE);
+        timeline.set_latency(0.0);
+        timeline.push(&[], start);
+        Ok(WavRecorder {
+            writer: WavWriter::new(inner, SAMPLE_RATE)?,
+            synth: Synth::new(tone, SAMPLE_RATE),
+            timeline,
+            start,
+        })
+    }
+
+    //Renders audio up to the emulator's current time.
+    pub fn push(&mut self, events: &[SoundEvent], now: f64) -> Result<(), WavError> {
+        self.timeline.push(events, now);
+        let target = ((now - self.start) * SAMPLE_RATE as f64 / TICK_RATE).round() as u32;
+        let count = target.saturating_sub(self.writer.samples()) as usize;
+        let mut samples = vec![0.0; count];
+        for sample in samples.iter_mut() {
+            let state = self.timeline.next_state();
+            self.synth.set_gate(state.on);
+            self.synth.set_pattern(state.pattern, state.pitch);
+            *sample = self.synth.next_sample();
+        }
+        self.writer.write_samples(&samples)
+    }
+
+    pub fn finish(self) -> Result<W, WavError> {
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sound::{SoundChange, SoundEvent, Tone};
+    use crate::wav::{WavRecorder, WavWriter};
+    use std::io::Cursor;
+
+    #[test]
+    fn header_test() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 8000).unwrap();
+        writer.write_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(&data[4..8], &(36u32 + 8).to_le_bytes());
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(&data[24..28], &8000u32.to_le_bytes());
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(&data[40..44], &8u32.to_le_bytes());
+        assert_eq!(&data[44..], &[0, 0, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn recorder_length_test() {
+        let mut recorder = WavRecorder::new(Cursor::new(Vec::new()), Tone::default(), 3.0).unwrap();
+        let events = [SoundEvent {
+            time: 3.5,
+            change: SoundChange::Beeper(true),
+        }];
+        recorder.push(&events, 4.0).unwrap();
+        recorder.push(&[], 5.0).unwrap();
+        let data = recorder.finish().unwrap().into_inner();
+        //Two ticks of 735 samples.
+        assert_eq!(data.len(), 44 + 2 * 735 * 2);
+        let samples: Vec<i16> = data[44..]
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert!(samples[..367].iter().all(|s| *s == 0));
+        assert!(samples[368..].iter().any(|s| *s != 0));
+    }
+}
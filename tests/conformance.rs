@@ -0,0 +1,213 @@
+use chip8forever::emulator::Emulator;
+use chip8forever::memmap::MemoryMap;
+use chip8forever::quirks::Quirks;
+use chip8forever::rom::Rom;
+use chip8forever::sound::{SoundChange, SoundEvent, Tone};
+use chip8forever::wav::WavRecorder;
+use std::io::Cursor;
+
+//Loads a hand-assembled program and runs it for the given number of frames.
+fn run(program: &[u8], quirks: Quirks, frames: u32) -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.set_quirks(quirks);
+    emulator.load(&Rom::from_bytes(program).unwrap()).unwrap();
+    emulator.run_frames(frames).unwrap();
+    emulator
+}
+
+#[test]
+fn fibonacci() {
+    let program = [
+        0x60, 0x00, // 200: V0 = 0
+        0x61, 0x01, // 202: V1 = 1
+        0x62, 0x0A, // 204: V2 = 10
+        0xA3, 0x00, // 206: I = 0x300
+        0xF0, 0x55, // 208: [I] = V0, I += 1
+        0x83, 0x00, // 20A: V3 = V0
+        0x83, 0x14, // 20C: V3 += V1
+        0x80, 0x10, // 20E: V0 = V1
+        0x81, 0x30, // 210: V1 = V3
+        0x72, 0xFF, // 212: V2 -= 1
+        0x32, 0x00, // 214: skip if V2 == 0
+        0x12, 0x08, // 216: jump 208
+        0x12, 0x18, // 218: halt
+    ];
+    let emulator = run(&program, Quirks::vip(), 20);
+    assert_eq!(
+        emulator.memory().read_range(0x300, 10),
+        &[0, 1, 1, 2, 3, 5, 8, 13, 21, 34]
+    );
+    assert_eq!(emulator.cpu().i(), 0x30A);
+    assert_eq!(emulator.cpu().pc(), 0x218);
+}
+
+#[test]
+fn eti660_memory_map() {
+    let program = [
+        0x60, 0x07, // 600: V0 = 7
+        0xF0, 0x29, // 602: I = font digit 7
+        0x16, 0x04, // 604: halt
+    ];
+    let mut emulator = Emulator::new();
+    emulator.set_memory_map(MemoryMap::eti660());
+    emulator.load(&Rom::from_bytes(&program).unwrap()).unwrap();
+    emulator.run_frames(1).unwrap();
+    assert_eq!(emulator.memory().read_range(0x600, 2), &[0x60, 0x07]);
+    assert_eq!(emulator.cpu().pc(), 0x604);
+    assert_eq!(emulator.cpu().i(), 7 * 5);
+    assert_eq!(
+        emulator.memory().read_range(35, 5),
+        &[0xF0, 0x10, 0x20, 0x40, 0x40]
+    );
+}
+
+#[test]
+fn nested_subroutines() {
+    let program = [
+        0x22, 0x08, // 200: call 208
+        0x65, 0x55, // 202: V5 = 0x55
+        0x12, 0x04, // 204: halt
+        0x00, 0x00, // 206:
+        0x70, 0x01, // 208: V0 += 1
+        0x22, 0x10, // 20A: call 210
+        0x22, 0x10, // 20C: call 210
+        0x00, 0xEE, // 20E: return
+        0x71, 0x02, // 210: V1 += 2
+        0x00, 0xEE, // 212: return
+    ];
+    let emulator = run(&program, Quirks::vip(), 2);
+    let cpu = emulator.cpu();
+    assert_eq!((cpu.reg(0), cpu.reg(1), cpu.reg(5)), (1, 4, 0x55));
+    assert_eq!(cpu.stack_depth(), 0);
+    assert_eq!(cpu.pc(), 0x204);
+}
+
+#[test]
+fn bcd_round_trip() {
+    let program = [
+        0x60, 0x7B, // 200: V0 = 123
+        0xA3, 0x00, // 202: I = 0x300
+        0xF0, 0x33, // 204: BCD V0
+        0xF2, 0x65, // 206: load V0-V2
+        0x12, 0x08, // 208: halt
+    ];
+    let emulator = run(&program, Quirks::schip(), 1);
+    let cpu = emulator.cpu();
+    assert_eq!((cpu.reg(0), cpu.reg(1), cpu.reg(2)), (1, 2, 3));
+    assert_eq!(emulator.memory().read_range(0x300, 3), &[1, 2, 3]);
+}
+
+#[test]
+fn delay_timer_countdown() {
+    let program = [
+        0x60, 0x0A, // 200: V0 = 10
+        0xF0, 0x15, // 202: DT = V0
+        0xF1, 0x07, // 204: V1 = DT
+        0x31, 0x00, // 206: skip if V1 == 0
+        0x12, 0x04, // 208: jump 204
+        0x62, 0x01, // 20A: V2 = 1
+        0x12, 0x0C, // 20C: halt
+    ];
+    let mut emulator = run(&program, Quirks::vip(), 5);
+    assert_eq!(emulator.cpu().dt(), 5);
+    assert_eq!(emulator.cpu().reg(2), 0);
+    emulator.run_frames(6).unwrap();
+    assert_eq!(emulator.cpu().dt(), 0);
+    assert_eq!(emulator.cpu().reg(2), 1);
+}
+
+#[test]
+fn store_load_quirks() {
+    //Stores V0-V2 and loads them back from wherever I ended up.
+    let program = [
+        0xA3, 0x00, // 200: I = 0x300
+        0x60, 0x11, // 202: V0 = 0x11
+        0x61, 0x22, // 204: V1 = 0x22
+        0x62, 0x33, // 206: V2 = 0x33
+        0xF2, 0x55, // 208: store V0-V2
+        0xF2, 0x65, // 20A: load V0-V2
+        0x12, 0x0C, // 20C: halt
+    ];
+    for &(quirks, registers, i) in &[
+        (Quirks::vip(), (0x00, 0x00, 0x00), 0x306),
+        (Quirks::chip48(), (0x33, 0x00, 0x00), 0x304),
+        (Quirks::schip(), (0x11, 0x22, 0x33), 0x300),
+    ] {
+        let emulator = run(&program, quirks, 1);
+        let cpu = emulator.cpu();
+        assert_eq!((cpu.reg(0), cpu.reg(1), cpu.reg(2)), registers);
+        assert_eq!(cpu.i(), i);
+        assert_eq!(emulator.memory().read_range(0x300, 3), &[0x11, 0x22, 0x33]);
+    }
+}
+
+#[test]
+fn shift_and_logic_quirks() {
+    let program = [
+        0x60, 0x10, // 200: V0 = 0x10
+        0x61, 0x03, // 202: V1 = 0x03
+        0x80, 0x16, // 204: V0 >>= 1
+        0x6F, 0x07, // 206: VF = 7
+        0x82, 0x11, // 208: V2 |= V1
+        0x12, 0x0A, // 20A: halt
+    ];
+    let emulator = run(&program, Quirks::vip(), 1);
+    let cpu = emulator.cpu();
+    assert_eq!((cpu.reg(0), cpu.reg(2), cpu.reg(0xF)), (0x01, 0x03, 0));
+    let emulator = run(&program, Quirks::schip(), 1);
+    let cpu = emulator.cpu();
+    assert_eq!((cpu.reg(0), cpu.reg(2), cpu.reg(0xF)), (0x08, 0x03, 7));
+}
+
+#[test]
+fn one_tick_beep() {
+    let program = [
+        0x60, 0x01, // 200: V0 = 1
+        0xF0, 0x18, // 202: ST = V0
+        0x12, 0x04, // 204: halt
+    ];
+    let mut emulator = run(&program, Quirks::vip(), 2);
+    //Second instruction of ten in the first tick, off when the timer ticks.
+    assert_eq!(
+        emulator.take_sound_events(),
+        vec![
+            SoundEvent {
+                time: 0.1,
+                change: SoundChange::Beeper(true)
+            },
+            SoundEvent {
+                time: 1.0,
+                change: SoundChange::Beeper(false)
+            },
+        ]
+    );
+    assert!(emulator.take_sound_events().is_empty());
+    assert_eq!(emulator.time(), 2.0);
+}
+
+#[test]
+fn one_tick_beep_audio() {
+    let program = [
+        0x60, 0x01, // 200: V0 = 1
+        0xF0, 0x18, // 202: ST = V0
+        0x12, 0x04, // 204: halt
+    ];
+    let mut emulator = run(&program, Quirks::vip(), 0);
+    let mut wav = WavRecorder::new(Cursor::new(Vec::new()), Tone::default(), 0.0).unwrap();
+    for _ in 0..3 {
+        emulator.run_frame().unwrap();
+        let events = emulator.take_sound_events();
+        wav.push(&events, emulator.time()).unwrap();
+    }
+    let data = wav.finish().unwrap().into_inner();
+    let samples: Vec<i16> = data[44..]
+        .chunks(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    assert_eq!(samples.len(), 3 * 735);
+    //On from 0.1 to 1.0 ticks, plus the fade out.
+    let first = samples.iter().position(|s| *s != 0).unwrap();
+    let last = samples.iter().rposition(|s| *s != 0).unwrap();
+    assert_eq!(first, 73);
+    assert!(last > 735 && last < 735 + 230, "{}", last);
+}
@@ -0,0 +1,54 @@
+use chip8forever::regression::{self, InputScript};
+use chip8forever::rom::Rom;
+use std::path::PathBuf;
+
+//Runs a bundled ROM and compares the final screen with tests/golden/<golden>.
+//Run with UPDATE_GOLDEN=1 to accept new output.
+fn check(rom: &str, frames: u32, script: &str, golden: &str) {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let rom = Rom::from_file(root.join("res").join(rom)).unwrap();
+    let script: InputScript = script.parse().unwrap();
+    let snapshot = regression::run(&rom, frames, &script).unwrap();
+    let golden = root.join("tests").join("golden").join(golden);
+    if let Err(diff) = regression::check_golden(golden, &snapshot) {
+        panic!("{}", diff);
+    }
+}
+
+#[test]
+fn pong_idle() {
+    //Paddles, ball and scores are all drawn at frame 120.
+    check("Pong.ch8", 120, "", "pong_idle.txt");
+}
+
+#[test]
+fn pong_paddle_up() {
+    //Pong waits 96 frames before the first serve. Pong redraws over several
+    //frames, 125 is one where both paddles and the ball are on screen.
+    let script = "
+        100 press 1
+        110 release 1
+    ";
+    check("Pong.ch8", 125, script, "pong_paddle_up.txt");
+}
+
+#[test]
+fn pong2_idle() {
+    //Both paddles are drawn, unlike frame 120.
+    check("pong2.ch8", 119, "", "pong2_idle.txt");
+}
+
+#[test]
+fn space_title() {
+    check("space.ch8", 60, "", "space_title.txt");
+}
+
+#[test]
+fn airplane_idle() {
+    check("Airplane.ch8", 180, "", "airplane_idle.txt");
+}
+
+#[test]
+fn demo_idle() {
+    check("demo.ch8", 300, "", "demo_idle.txt");
+}